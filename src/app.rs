@@ -1,13 +1,22 @@
 
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use std::collections::HashMap;
 use winit::application::ApplicationHandler;
 use winit::event::*;
 use winit::event_loop::ActiveEventLoop;
 use winit::window::{Window, WindowId};
 use crate::wgpu_context::WgpuContext;
+use crate::input::{Action, Input};
+#[cfg(feature = "gamepad")]
+use crate::gamepad::Gamepads;
 use crate::player;
+use crate::cli::Args;
+use crate::error::TerrainError;
+use crate::screenshot;
+use crate::settings::{Settings, SettingsWatcher};
+use crate::terrain::ground::Ground;
+use crate::time_of_day::TimeOfDay;
+use crate::hud::SpeedIndicator;
 
 #[derive(Default)]
 struct MouseGrabber {
@@ -16,11 +25,15 @@ struct MouseGrabber {
 }
 
 // winit does not support cursor locking on win10, so we need to make it ourselves
+// and some platforms (wayland) can't move the cursor either, there we just leave it free and only hide it
 
 impl MouseGrabber {
 	fn cursor_moved(&mut self, window: &Window, pos: winit::dpi::PhysicalPosition<f64>) {
 		if self.manual_lock {
-			window.set_cursor_position(self.last_pos).unwrap();
+			if let Err(err) = window.set_cursor_position(self.last_pos) {
+				eprintln!("Can't keep the cursor in place, leaving it free: {}", err);
+				self.manual_lock = false;
+			}
 		} else {
 			self.last_pos = pos;
 		}
@@ -29,12 +42,15 @@ impl MouseGrabber {
 	fn grab(&mut self, window: &Window, grab: bool) {
 		if grab {
 			if window.set_cursor_grab(winit::window::CursorGrabMode::Locked).is_err() {
-				window.set_cursor_grab(winit::window::CursorGrabMode::Confined).unwrap();
+				if let Err(err) = window.set_cursor_grab(winit::window::CursorGrabMode::Confined) {
+					eprintln!("Can't lock or confine the cursor: {}", err);
+				}
 				self.manual_lock = true;
 			}
 		} else {
 			self.manual_lock = false;
-			window.set_cursor_grab(winit::window::CursorGrabMode::None).unwrap();
+			// releasing only fails if grabbing failed before, nothing left to do then
+			let _ = window.set_cursor_grab(winit::window::CursorGrabMode::None);
 		}
 		window.set_cursor_visible(!grab);
 	}
@@ -45,15 +61,128 @@ pub struct App<'window> {
     dt_start: Option<Instant>,
     dt: Duration,
     player: player::Player,
-    keys: HashMap<u8, bool>,
+    time_of_day: TimeOfDay,
+    ground: Ground, // what the player walks on
+    input: Input,
+    speed_indicator: SpeedIndicator,
+    #[cfg(feature = "gamepad")]
+    gamepads: Gamepads,
     window: Option<Arc<Window>>,
     wgpu_ctx: Option<WgpuContext<'window>>,
-    mouse_grabber: MouseGrabber
+    mouse_grabber: MouseGrabber,
+    settings: Settings,
+    args: Args,
+    settings_watcher: SettingsWatcher,
+    error: Option<TerrainError>
 }
 
 impl App<'_> {
-    pub fn init(&mut self) {
-        self.player = player::Player::new();
+    pub fn init(&mut self, settings: Settings, args: Args) {
+        self.player = player::Player::new(&settings.player);
+        self.time_of_day = TimeOfDay::new(&settings.time);
+        self.ground = Ground::new(&settings);
+        self.input = Input::new(&settings.keys);
+        #[cfg(feature = "gamepad")]
+        {
+            self.gamepads = Gamepads::new();
+        }
+        println!("Using seed {}", settings.seed);
+        self.settings_watcher = SettingsWatcher::new(args.config.clone());
+        self.settings = settings;
+        self.args = args;
+    }
+
+    // whatever made the event loop exit early
+    pub fn take_error(&mut self) -> Option<TerrainError> {
+        return self.error.take();
+    }
+
+    fn fail(&mut self, event_loop: &ActiveEventLoop, err: TerrainError) {
+        self.error = Some(err);
+        event_loop.exit();
+    }
+
+    // F2, the png is written on another thread so the game doesn't hitch
+    fn take_screenshot(&mut self) {
+        let Some(wgpu_ctx) = self.wgpu_ctx.as_mut() else {
+            return;
+        };
+        let (width, height, pixels) = match wgpu_ctx.capture_tiled(self.settings.screenshot.scale) {
+            Ok(capture) => capture,
+            Err(err) => {
+                eprintln!("Screenshot failed: {}", err);
+                return;
+            }
+        };
+        let directory = self.settings.screenshot.directory.clone();
+        std::thread::spawn(move || {
+            if let Err(err) = std::fs::create_dir_all(&directory) {
+                eprintln!("Screenshot failed: could not create {}: {}", directory.display(), err);
+                return;
+            }
+            let path = screenshot::timestamped_path(&directory);
+            match screenshot::save_png(&path, width, height, &pixels) {
+                Ok(()) => println!("Saved {}x{} screenshot to {}", width, height, path.display()),
+                Err(err) => eprintln!("Screenshot failed: {}", err)
+            }
+        });
+    }
+
+    // the actions that happen once per key press, the held ones are asked for where they're used
+    fn trigger(&mut self, action: Action) {
+        match action {
+            Action::Screenshot => {
+                self.take_screenshot();
+            }
+            Action::PauseTime => {
+                let time = &mut self.time_of_day;
+                time.paused = !time.paused;
+                println!("Time {} at {:02}:{:02}", if time.paused { "paused" } else { "running" }, time.hour as u32, (time.hour.fract() * 60.) as u32);
+            }
+            Action::ToggleWalk => {
+                self.player.toggle_walking();
+                println!("{}", if self.player.walking { "Walking" } else { "Flying" });
+            }
+            Action::ReleaseMouse => {
+                if let Some(window) = self.window.as_ref() {
+                    self.mouse_grabber.grab(window, false);
+                }
+            }
+            _ => ()
+        }
+    }
+
+    fn reload_settings(&mut self) {
+        // load() falls back to defaults for a missing file, which is right at startup but would reset everything here
+        if !self.settings_watcher.path().exists() {
+            eprintln!("{} is gone, keeping the current settings", self.settings_watcher.path().display());
+            return;
+        }
+        let mut settings = match Settings::load(self.settings_watcher.path()) {
+            Ok(settings) => settings,
+            Err(err) => {
+                // keep running with the old settings, the file is probably just half saved
+                eprintln!("{}", err);
+                return;
+            }
+        };
+        self.args.apply(&mut settings);
+        if settings == self.settings {
+            return;
+        }
+        println!("Reloaded {}", self.settings_watcher.path().display());
+        if settings.window != self.settings.window {
+            println!("Window settings only apply after a restart");
+        }
+
+        self.player.apply_settings(&settings.player);
+        self.time_of_day.apply_settings(&settings.time);
+        self.ground = Ground::new(&settings);
+        self.input.set_bindings(&settings.keys);
+        if let Some(wgpu_ctx) = self.wgpu_ctx.as_mut() {
+            wgpu_ctx.apply_settings(&settings);
+        }
+        self.settings = settings;
     }
 }
 
@@ -61,10 +190,30 @@ impl<'window> ApplicationHandler for App<'window> {
 
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.window.is_none() {
-            let win_attr = Window::default_attributes().with_title("Rust Terrain Generation");
-            let window = Arc::new(event_loop.create_window(win_attr).expect("Error creating window."));
+            let mut win_attr = Window::default_attributes().with_title("Rust Terrain Generation");
+            let window_settings = &self.settings.window;
+            if window_settings.width.is_some() || window_settings.height.is_some() {
+                // if only one of them is given, the other one falls back to the winit default
+                win_attr = win_attr.with_inner_size(winit::dpi::PhysicalSize::new(window_settings.width.unwrap_or(800), window_settings.height.unwrap_or(600)));
+            }
+            if window_settings.fullscreen {
+                win_attr = win_attr.with_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+            }
+            let window = match event_loop.create_window(win_attr) {
+                Ok(window) => Arc::new(window),
+                Err(err) => {
+                    return self.fail(event_loop, err.into());
+                }
+            };
             self.window = Some(window.clone());
-            self.wgpu_ctx = Some(WgpuContext::new(window.clone()));
+            match WgpuContext::new(window.clone(), &self.settings) {
+                Ok(wgpu_ctx) => {
+                    self.wgpu_ctx = Some(wgpu_ctx);
+                }
+                Err(err) => {
+                    return self.fail(event_loop, err);
+                }
+            }
             self.mouse_grabber.grab(&window, true);
         }
     }
@@ -83,54 +232,85 @@ impl<'window> ApplicationHandler for App<'window> {
                 }
             }
             WindowEvent::RedrawRequested => {
+                #[cfg(feature = "gamepad")]
+                for action in self.gamepads.poll(&mut self.input, &self.settings.gamepad) {
+                    self.trigger(action);
+                }
                 if let Some(wgpu_ctx) = self.wgpu_ctx.as_mut() {
-                    if self.dt_start.is_some() {
-                        self.dt = self.dt_start.unwrap().elapsed();
+                    if let Some(dt_start) = self.dt_start {
+                        self.dt = dt_start.elapsed();
                     }
                     else {
                         self.dt = Duration::new(0, 0);
                     }
                     self.dt_start = Some(Instant::now());
+
+                    let dt = self.dt.as_secs_f32();
+                    let [yaw, pitch] = self.input.look();
+                    let look_speed = self.settings.gamepad.look_speed * dt;
+                    self.player.turn(yaw * look_speed, pitch * look_speed);
+                    let scrub = self.input.held(Action::TimeForward) as i32 - self.input.held(Action::TimeBack) as i32;
+                    self.time_of_day.scrub(scrub as f32 * TimeOfDay::SCRUB_SPEED * dt);
+                    self.time_of_day.advance(dt);
+                    wgpu_ctx.set_light(self.time_of_day.light(&self.settings.light));
+                    let fly_speed = (!self.player.walking).then(|| self.player.fly_speed(&self.input));
+                    self.speed_indicator.update(fly_speed, dt);
+                    wgpu_ctx.set_speed_indicator(self.speed_indicator);
                     
                     wgpu_ctx.update(self.dt, &(self.player));
                     
-                    wgpu_ctx.draw();
-                    self.player.update(&self.keys, self.dt.as_secs_f32());
+                    if let Err(err) = wgpu_ctx.draw() {
+                        return self.fail(event_loop, err);
+                    }
+                    self.player.update(&self.input, self.dt.as_secs_f32(), &self.ground);
                 }
             }
-            WindowEvent::KeyboardInput {device_id: _, event, is_synthetic} => {
-                if !is_synthetic {
-                    if event.logical_key.to_text().unwrap_or(&"") == "\x1b" {
-                        self.mouse_grabber.grab(self.window.as_mut().unwrap(), false);
-                    }
-                    if event.logical_key.to_text().is_some() {
-                        self.keys.insert((event.logical_key.to_text().unwrap().as_bytes()[0]) as u8, event.state == ElementState::Pressed);
+            WindowEvent::KeyboardInput {device_id: _, event, is_synthetic} if !is_synthetic => {
+                let winit::keyboard::PhysicalKey::Code(key) = event.physical_key else {
+                    return;
+                };
+                let pressed = event.state == ElementState::Pressed;
+                self.input.key_event(key, pressed);
+                if pressed && !event.repeat {
+                    for action in self.input.actions(key) {
+                        self.trigger(action);
                     }
                 }
             }
+            WindowEvent::Focused(false) => {
+                self.input.release_all();
+            }
+            WindowEvent::MouseWheel { device_id: _, delta, phase: _ } => {
+                // touchpads scroll in pixels, about this many make a notch
+                let notches = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.
+                };
+                self.player.scroll(notches);
+            }
             WindowEvent::CursorMoved { device_id: _, position } => {
                 self.mouse_grabber.cursor_moved(self.window.as_mut().unwrap(), position);
             }
-            WindowEvent::MouseInput { device_id: _, state: _, button } => {
-                if button == winit::event::MouseButton::Left {
-                    self.mouse_grabber.grab(self.window.as_mut().unwrap(), true);
-                }
+            WindowEvent::MouseInput { device_id: _, state: _, button: winit::event::MouseButton::Left } => {
+                self.mouse_grabber.grab(self.window.as_mut().unwrap(), true);
             }
             _ => (),
         }
     }
 
     fn device_event(&mut self, _event_loop: &ActiveEventLoop, _device_id: DeviceId, event: DeviceEvent) {
-        match event {
-            DeviceEvent::MouseMotion { delta } => {
-                self.player.move_camera(delta.0 as f32, delta.1 as f32);
-            }
-            _ => ()
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.player.move_camera(delta.0 as f32, delta.1 as f32);
         }
     }
 
     fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
-        self.window.as_mut().unwrap().request_redraw();
+        if self.settings_watcher.changed() {
+            self.reload_settings();
+        }
+        if let Some(window) = self.window.as_ref() {
+            window.request_redraw();
+        }
     }
 
 }
\ No newline at end of file
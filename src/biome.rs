@@ -0,0 +1,81 @@
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+
+// which material the ground gets, see biome_color in shader.wgsl for how these are combined
+// heights are in the same units as the terrain (0 to about 1), slopes are height change per world unit
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Biomes {
+    pub beach_height: f32, // sand up to this far above the water level (see Water), and below it
+    pub snow_height: f32, // snow above, lower where it's cold
+    pub rock_slope: f32, // steeper than this is rock
+    pub climate_scale: f32, // frequency of the moisture and temperature noise, smaller is bigger biomes
+    pub blend: f32, // how soft the height based borders are
+    pub colors: ColorRamps
+}
+
+impl Default for Biomes {
+    fn default() -> Self {
+        Self {
+            beach_height: 0.03,
+            snow_height: 0.8,
+            rock_slope: 1.8,
+            climate_scale: 0.08,
+            blend: 0.02,
+            colors: ColorRamps::default()
+        }
+    }
+}
+
+// srgb colors, every ramp goes from its dry to its wet color with moisture
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorRamps {
+    pub sand: [[f32; 3]; 2],
+    pub grass: [[f32; 3]; 2],
+    pub rock: [[f32; 3]; 2],
+    pub snow: [[f32; 3]; 2]
+}
+
+impl Default for ColorRamps {
+    fn default() -> Self {
+        Self {
+            sand: [[0.86, 0.78, 0.55], [0.7, 0.62, 0.45]],
+            grass: [[0.55, 0.55, 0.25], [0.18, 0.42, 0.12]],
+            rock: [[0.5, 0.46, 0.42], [0.35, 0.34, 0.33]],
+            snow: [[0.95, 0.96, 0.98], [0.85, 0.9, 0.97]]
+        }
+    }
+}
+
+// the layout has to match the Biomes struct in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct BiomeUniform {
+    beach_height: f32,
+    snow_height: f32,
+    rock_slope: f32,
+    climate_scale: f32,
+    blend: f32,
+    _padding: [f32; 3],
+    colors: [[f32; 4]; 8] // two per ramp, in the order of ColorRamps
+}
+
+impl Biomes {
+    pub fn uniform(&self) -> BiomeUniform {
+        let ramps = [self.colors.sand, self.colors.grass, self.colors.rock, self.colors.snow];
+        let mut colors = [[0.; 4]; 8];
+        for (i, color) in ramps.iter().flatten().enumerate() {
+            colors[i] = [color[0], color[1], color[2], 1.];
+        }
+        return BiomeUniform {
+            beach_height: self.beach_height,
+            snow_height: self.snow_height,
+            rock_slope: self.rock_slope,
+            climate_scale: self.climate_scale,
+            blend: self.blend,
+            _padding: [0.; 3],
+            colors
+        };
+    }
+}
@@ -0,0 +1,215 @@
+use crate::settings::WalkSettings;
+use crate::terrain::ground::Ground;
+use crate::utils::{dot, magnitude};
+use crate::vector::Vector3;
+
+// what the player wants to do this frame
+#[derive(Copy, Clone, Default)]
+pub struct CharacterInput {
+    pub direction: Vector3, // flat on the ground, at most 1 long
+    pub jump: bool,
+    pub sprint: bool,
+    pub crouch: bool
+}
+
+// walking on the terrain as a capsule standing on the heightfield
+// the terrain has no overhangs, so only the sphere at the bottom of the capsule can ever touch it
+#[derive(Default)]
+pub struct CharacterController {
+    velocity: Vector3,
+    eye_height: f32, // moves towards the crouching or standing one
+    grounded: bool,
+    steep: bool // on a slope steeper than max_slope, sliding down it
+}
+
+// changes current by at most max_change towards target
+fn move_towards(current: Vector3, target: Vector3, max_change: f32) -> Vector3 {
+    let difference = target - current;
+    let distance = magnitude(difference);
+    if distance <= max_change {
+        return target;
+    }
+    return current + difference * (max_change / distance);
+}
+
+impl CharacterController {
+    // how quickly crouching and standing up happen, higher is faster
+    const CROUCH_RATE: f32 = 10.;
+    // the ground is sampled under the capsule and on a ring this far out, as a part of its radius
+    const SAMPLE_RING: f32 = 0.7;
+
+    pub fn new(settings: &WalkSettings) -> Self {
+        return Self { eye_height: settings.eye_height, ..Default::default() };
+    }
+
+    pub fn eye_height(&self) -> f32 {
+        return self.eye_height;
+    }
+
+    // for switching to walking, keeps the crouch
+    pub fn stop(&mut self) {
+        self.velocity = Vector3::default();
+        self.grounded = false;
+        self.steep = false;
+    }
+
+    // lowest the feet can go at the horizontal position of feet, the bottom sphere just resting on the ground
+    fn lowest_feet(feet: Vector3, ground: &Ground, radius: f32) -> f32 {
+        let mut center = ground.height(feet.x, feet.z) + radius;
+        let ring = Self::SAMPLE_RING * radius;
+        let lift = (radius * radius - ring * ring).sqrt();
+        for i in 0..8 {
+            let angle = i as f32 * std::f32::consts::FRAC_PI_4;
+            center = center.max(ground.height(feet.x + ring * angle.cos(), feet.z + ring * angle.sin()) + lift);
+        }
+        return center - radius;
+    }
+
+    // moves the feet for one frame, returns where they end up
+    pub fn update(&mut self, feet: Vector3, input: CharacterInput, dt: f32, ground: &Ground, settings: &WalkSettings) -> Vector3 {
+        let target_eye_height = if input.crouch { settings.crouch_eye_height } else { settings.eye_height };
+        self.eye_height += (target_eye_height - self.eye_height) * (Self::CROUCH_RATE * dt).min(1.);
+
+        let [_, slope_x, slope_z] = ground.sample(feet.x, feet.z);
+        let gradient = Vector3::new(slope_x, 0., slope_z);
+        let steepness = magnitude(gradient); // tan of the slope angle
+        let uphill = if steepness > 0. { gradient / steepness } else { gradient };
+        self.steep = self.grounded && steepness > settings.max_slope.to_radians().tan();
+
+        let speed = if input.crouch { settings.crouch_speed } else if input.sprint { settings.sprint_speed } else { settings.speed };
+        let mut wish = input.direction * speed;
+        let climb = dot(wish, uphill);
+        if self.steep && climb > 0. {
+            // along the slope or down it is fine, up it is not
+            wish -= uphill * climb;
+        }
+
+        // speeds up towards where the player wants to go and slows down with friction when they let go
+        // in the air there's only a bit of control and nothing to slow down on
+        let mut horizontal = Vector3::new(self.velocity.x, 0., self.velocity.z);
+        let wants_to_move = magnitude(input.direction) > 0.;
+        if self.grounded {
+            let rate = if wants_to_move { settings.acceleration } else { settings.friction };
+            horizontal = move_towards(horizontal, wish, rate * dt);
+        } else if wants_to_move {
+            horizontal = move_towards(horizontal, wish, settings.acceleration * settings.air_control * dt);
+        }
+        if self.steep {
+            // the part of gravity along the slope, flattened, the ground keeps the height right
+            let cos = 1. / (1. + steepness * steepness).sqrt();
+            horizontal -= uphill * (settings.gravity * steepness * cos * cos * dt);
+        }
+        self.velocity = Vector3::new(horizontal.x, self.velocity.y, horizontal.z);
+
+        if input.jump && self.grounded && !self.steep {
+            self.velocity.y = settings.jump_speed;
+        }
+        self.velocity.y -= settings.gravity * dt;
+        let previous = feet;
+        let mut feet = feet + self.velocity * dt;
+
+        // a walkable slope can only rise so much over the distance moved, anything more is a ledge
+        // measured from where the feet were, so it also stops landing against a cliff from putting the player on top of it
+        let mut lowest = Self::lowest_feet(feet, ground, settings.radius);
+        let moved = magnitude(Vector3::new(feet.x - previous.x, 0., feet.z - previous.z));
+        if lowest - previous.y > settings.step_height + moved * settings.max_slope.to_radians().tan() {
+            feet = Vector3::new(previous.x, feet.y, previous.z);
+            self.velocity = Vector3::new(0., self.velocity.y, 0.);
+            lowest = Self::lowest_feet(feet, ground, settings.radius);
+        }
+
+        // going downhill sticks to the ground instead of flying off every small bump, unless it falls away too far
+        let was_grounded = self.grounded;
+        self.grounded = feet.y <= lowest || (was_grounded && self.velocity.y <= 0. && feet.y - lowest <= settings.snap_distance);
+        if self.grounded {
+            feet.y = lowest;
+            self.velocity.y = 0.;
+        }
+        return feet;
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::settings::Settings;
+
+    // first point on a grid from the origin where the slope passes the test
+    pub fn find_slope(ground: &Ground, test: impl Fn(f32) -> bool) -> Vector3 {
+        for x in 0..200 {
+            for z in 0..200 {
+                let (x, z) = (x as f32 * 0.01, z as f32 * 0.01);
+                let [height, slope_x, slope_z] = ground.sample(x, z);
+                if test((slope_x * slope_x + slope_z * slope_z).sqrt()) {
+                    return Vector3::new(x, height, z);
+                }
+            }
+        }
+        panic!("no slope like that to test on");
+    }
+
+    fn settle(controller: &mut CharacterController, mut feet: Vector3, input: CharacterInput, frames: u32, ground: &Ground, settings: &WalkSettings) -> Vector3 {
+        for _ in 0..frames {
+            feet = controller.update(feet, input, 1. / 60., ground, settings);
+        }
+        return feet;
+    }
+
+    #[test]
+    fn speeds_up_and_slows_down() {
+        let settings = Settings::default();
+        let (ground, walk) = (Ground::new(&settings), settings.player.walk);
+        let mut controller = CharacterController::new(&walk);
+        let flat = find_slope(&ground, |steepness| steepness < 0.1);
+        let feet = settle(&mut controller, flat + Vector3::new(0., 0.2, 0.), CharacterInput::default(), 60, &ground, &walk);
+        assert!(controller.grounded);
+
+        let forward = CharacterInput { direction: Vector3::new(1., 0., 0.), ..Default::default() };
+        settle(&mut controller, feet, forward, 1, &ground, &walk);
+        let speed = magnitude(Vector3::new(controller.velocity.x, 0., controller.velocity.z));
+        assert!(speed > 0. && speed < walk.speed);
+
+        let feet = settle(&mut controller, feet, CharacterInput::default(), 60, &ground, &walk);
+        assert_eq!(magnitude(Vector3::new(controller.velocity.x, 0., controller.velocity.z)), 0.);
+        assert!((feet.y - CharacterController::lowest_feet(feet, &ground, walk.radius)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn slides_down_slopes_that_are_too_steep() {
+        let settings = Settings::default();
+        let (ground, walk) = (Ground::new(&settings), settings.player.walk);
+        let max_steepness = walk.max_slope.to_radians().tan();
+        let start = find_slope(&ground, |steepness| steepness > 1.5 * max_steepness);
+
+        let mut controller = CharacterController::new(&walk);
+        controller.grounded = true;
+        let feet = settle(&mut controller, start, CharacterInput::default(), 30, &ground, &walk);
+        assert!(feet.y < CharacterController::lowest_feet(start, &ground, walk.radius));
+    }
+
+    #[test]
+    fn does_not_climb_ledges_above_the_step_height() {
+        let settings = Settings::default();
+        let (ground, walk) = (Ground::new(&settings), settings.player.walk);
+        let (dt, max_steepness) = (1. / 60., walk.max_slope.to_radians().tan());
+
+        // somewhere that following the ground while carried uphill at this speed would lift the player by more than a step
+        let ledge = |x: f32, z: f32| {
+            let [_, slope_x, slope_z] = ground.sample(x, z);
+            let uphill = Vector3::new(slope_x, 0., slope_z) / magnitude(Vector3::new(slope_x, 0., slope_z));
+            let start = Vector3::new(x, 0., z);
+            let start = Vector3::new(x, CharacterController::lowest_feet(start, &ground, walk.radius), z);
+            let velocity = uphill * 1.2;
+            let rise = CharacterController::lowest_feet(start + velocity * dt, &ground, walk.radius) - start.y;
+            return (rise > walk.step_height + magnitude(velocity * dt) * max_steepness).then_some((start, velocity));
+        };
+        let (start, velocity) = (0..40000).find_map(|i| ledge((i / 200) as f32 * 0.01, (i % 200) as f32 * 0.01)).expect("no ledge to test on");
+
+        let mut controller = CharacterController::new(&walk);
+        controller.grounded = true;
+        controller.velocity = velocity;
+        let feet = controller.update(start, CharacterInput::default(), dt, &ground, &walk);
+        assert_eq!((feet.x, feet.z), (start.x, start.z));
+        assert!(feet.y <= start.y);
+    }
+}
@@ -0,0 +1,159 @@
+use std::path::PathBuf;
+use clap::{Parser, Subcommand};
+use crate::export::heightmap::{HeightmapFormat, Region, Resolution};
+use crate::export::mesh::MeshFormat;
+use crate::settings::{ScreenshotSettings, Settings};
+use crate::vertex_data::{Quality, RenderDistance};
+
+#[derive(Parser, Debug, Clone, Default)]
+#[command(version, about = "Procedural terrain, rendered with wgpu")]
+pub struct Args {
+    /// Settings file, reloaded while running whenever it changes
+    #[arg(long, default_value = Settings::DEFAULT_PATH, global = true)]
+    pub config: PathBuf,
+
+    /// Vertex density of the terrain
+    #[arg(long, value_enum)]
+    pub quality: Option<Quality>,
+
+    /// How far the terrain reaches
+    #[arg(long, value_enum)]
+    pub render_distance: Option<RenderDistance>,
+
+    /// World seed, the same seed always gives the same world
+    #[arg(long, global = true)]
+    pub seed: Option<u64>,
+
+    /// Window width in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: Option<u32>,
+
+    /// Window height in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub height: Option<u32>,
+
+    /// Start in borderless fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Render a single frame without a window and write it to --output
+    #[arg(long)]
+    pub headless: bool,
+
+    /// Ask for a software renderer, for --headless on machines without a gpu
+    #[arg(long)]
+    pub fallback_adapter: bool,
+
+    /// Camera position for --headless as x,y,z
+    #[arg(long, default_value = "0,0,0", value_parser = parse_floats::<3>, allow_hyphen_values = true)]
+    pub camera: [f32; 3],
+
+    /// Camera rotation for --headless as yaw,pitch in degrees
+    #[arg(long, default_value = "0,-20", value_parser = parse_floats::<2>, allow_hyphen_values = true)]
+    pub look: [f32; 2],
+
+    /// Time of day to start at, in hours (0-24)
+    #[arg(long)]
+    pub time: Option<f32>,
+
+    /// Render screenshots (F2 and --headless) at this many times the window resolution
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=ScreenshotSettings::MAX_SCALE as i64))]
+    pub screenshot_scale: Option<u32>,
+
+    /// Where --headless writes the png
+    #[arg(long, default_value = "headless.png")]
+    pub output: PathBuf,
+
+    #[command(subcommand)]
+    pub command: Option<Command>
+}
+
+// without a command the terrain is rendered in a window
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Write the terrain heights of a region to an image, without opening a window
+    ExportHeightmap(HeightmapArgs),
+    /// Write a region of the terrain as a mesh with normals, without opening a window
+    ExportMesh(MeshArgs)
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct HeightmapArgs {
+    /// Part of the world to export as x0,z0,x1,z1 in world coordinates
+    #[arg(long, default_value = "-5,-5,5,5", allow_hyphen_values = true)]
+    pub region: Region,
+
+    /// Pixels, either one number for a square or WIDTHxHEIGHT
+    #[arg(long, default_value = "1024")]
+    pub resolution: Resolution,
+
+    /// File format, guessed from the extension of --output if not given
+    #[arg(long, value_enum)]
+    pub format: Option<HeightmapFormat>,
+
+    /// Where to write the heightmap (.png, .r16/.raw, .pfm or .exr)
+    #[arg(short, long)]
+    pub output: PathBuf
+}
+
+// "1,2.5,-3", nan and inf parse as floats but are never what was meant
+pub fn parse_floats<const N: usize>(s: &str) -> Result<[f32; N], String> {
+    let values: Vec<f32> = s.split(',').map(|v| v.trim().parse::<f32>()).collect::<Result<_, _>>().map_err(|err| err.to_string())?;
+    if let Some(value) = values.iter().find(|v| !v.is_finite()) {
+        return Err(format!("{} is not a number", value));
+    }
+    return values.try_into().map_err(|_| format!("expected {} comma separated numbers", N));
+}
+
+impl Args {
+    // only touches what was actually passed on the command line, so the flags win over the settings file
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(quality) = self.quality {
+            settings.quality = quality;
+        }
+        if let Some(render_distance) = self.render_distance {
+            settings.render_distance = render_distance;
+        }
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        if let Some(width) = self.width {
+            settings.window.width = Some(width);
+        }
+        if let Some(height) = self.height {
+            settings.window.height = Some(height);
+        }
+        if self.fullscreen {
+            settings.window.fullscreen = true;
+        }
+        if let Some(time) = self.time {
+            settings.time.start_hour = time;
+        }
+        if let Some(scale) = self.screenshot_scale {
+            settings.screenshot.scale = scale;
+        }
+    }
+}
+
+#[derive(clap::Args, Debug, Clone)]
+pub struct MeshArgs {
+    /// Part of the world to export as x0,z0,x1,z1 in world coordinates
+    #[arg(long, default_value = "-5,-5,5,5", allow_hyphen_values = true)]
+    pub region: Region,
+
+    /// Vertices, either one number for a square grid or WIDTHxHEIGHT
+    #[arg(long, default_value = "256")]
+    pub resolution: Resolution,
+
+    /// Multiplies the heights, the renderer uses 1
+    #[arg(long, default_value_t = 1.)]
+    pub height_scale: f32,
+
+    /// File format, guessed from the extension of --output if not given
+    #[arg(long, value_enum)]
+    pub format: Option<MeshFormat>,
+
+    /// Where to write the mesh (.obj, .ply or .glb)
+    #[arg(short, long)]
+    pub output: PathBuf
+}
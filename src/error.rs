@@ -0,0 +1,69 @@
+use std::fmt;
+use std::path::PathBuf;
+
+// everything that can go wrong before or while rendering, printed to the user instead of a panic
+#[derive(Debug)]
+pub enum TerrainError {
+    Settings { path: PathBuf, reason: String },
+    EventLoop(winit::error::EventLoopError),
+    Window(winit::error::OsError),
+    CreateSurface(wgpu::CreateSurfaceError),
+    NoAdapter,
+    UnsupportedSurface,
+    RequestDevice(wgpu::RequestDeviceError),
+    // only the surface errors we can't recover from by reconfiguring end up here
+    Surface(wgpu::SurfaceError),
+    Readback(wgpu::BufferAsyncError),
+    // captures are read back as 8 bit rgba, hdr and 10 bit surfaces can't be
+    CaptureFormat(wgpu::TextureFormat),
+    Io { path: PathBuf, err: std::io::Error },
+    UnknownFormat(PathBuf),
+    // the size math or the file format can't hold this many vertices or pixels
+    TooLarge(String)
+}
+
+impl fmt::Display for TerrainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TerrainError::Settings { path, reason } => write!(f, "invalid settings in {}: {}", path.display(), reason),
+            TerrainError::EventLoop(err) => write!(f, "event loop failed: {}", err),
+            TerrainError::Window(err) => write!(f, "could not create the window: {}", err),
+            TerrainError::CreateSurface(err) => write!(f, "could not create a surface for the window: {}", err),
+            TerrainError::NoAdapter => write!(f, "no graphics adapter found, make sure your gpu drivers support vulkan, metal, dx12 or opengl"),
+            TerrainError::UnsupportedSurface => write!(f, "the graphics adapter can't present to this window"),
+            TerrainError::RequestDevice(err) => write!(f, "could not open the graphics device: {}", err),
+            TerrainError::Surface(err) => write!(f, "could not get the next frame: {}", err),
+            TerrainError::Readback(err) => write!(f, "could not read the frame back from the gpu: {}", err),
+            TerrainError::CaptureFormat(format) => write!(f, "screenshots don't support the {:?} surface format", format),
+            TerrainError::Io { path, err } => write!(f, "could not write {}: {}", path.display(), err),
+            TerrainError::UnknownFormat(path) => write!(f, "don't know which format to write {} in, use a known extension or --format", path.display()),
+            TerrainError::TooLarge(what) => write!(f, "{} is too large", what)
+        }
+    }
+}
+
+impl std::error::Error for TerrainError {}
+
+impl From<winit::error::EventLoopError> for TerrainError {
+    fn from(err: winit::error::EventLoopError) -> Self {
+        return TerrainError::EventLoop(err);
+    }
+}
+
+impl From<winit::error::OsError> for TerrainError {
+    fn from(err: winit::error::OsError) -> Self {
+        return TerrainError::Window(err);
+    }
+}
+
+impl From<wgpu::CreateSurfaceError> for TerrainError {
+    fn from(err: wgpu::CreateSurfaceError) -> Self {
+        return TerrainError::CreateSurface(err);
+    }
+}
+
+impl From<wgpu::RequestDeviceError> for TerrainError {
+    fn from(err: wgpu::RequestDeviceError) -> Self {
+        return TerrainError::RequestDevice(err);
+    }
+}
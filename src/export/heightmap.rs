@@ -0,0 +1,356 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::str::FromStr;
+use crate::cli::{self, HeightmapArgs};
+use crate::error::TerrainError;
+use crate::settings::Settings;
+use crate::terrain::noise::{self, NoiseParams, NoiseSeed};
+
+// a rectangle of the world, x and z
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Region {
+    pub min: [f32; 2],
+    pub max: [f32; 2]
+}
+
+// "x0,z0,x1,z1"
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = cli::parse_floats::<4>(s)?;
+        if values[0] == values[2] || values[1] == values[3] {
+            return Err(String::from("the region is empty"));
+        }
+        return Ok(Self {
+            min: [values[0].min(values[2]), values[1].min(values[3])],
+            max: [values[0].max(values[2]), values[1].max(values[3])]
+        });
+    }
+}
+
+// "512" for a square or "1024x512"
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32
+}
+
+impl FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (width, height) = s.split_once('x').unwrap_or((s, s));
+        let width: u32 = width.trim().parse().map_err(|_| format!("invalid width '{}'", width))?;
+        let height: u32 = height.trim().parse().map_err(|_| format!("invalid height '{}'", height))?;
+        if width < 2 || height < 2 {
+            return Err(String::from("the resolution has to be at least 2x2"));
+        }
+        return Ok(Self { width, height });
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum HeightmapFormat {
+    #[value(name = "png")]
+    Png16, // 16 bit grayscale, normalized to the sampled range
+    R16, // raw little endian u16, normalized to the sampled range (what unity and world machine read)
+    Pfm, // 32 bit float, unnormalized
+    Exr // 32 bit float, unnormalized
+}
+
+impl HeightmapFormat {
+    // exr stores sizes as i32, the others can take anything that fits in memory
+    pub fn supports(self, resolution: Resolution) -> bool {
+        if self == HeightmapFormat::Exr {
+            return i32::try_from(4 * resolution.width as u64).is_ok() && i32::try_from(resolution.height).is_ok();
+        }
+        return true;
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "png" => {
+                return Some(HeightmapFormat::Png16);
+            }
+            "r16" | "raw" => {
+                return Some(HeightmapFormat::R16);
+            }
+            "pfm" => {
+                return Some(HeightmapFormat::Pfm);
+            }
+            "exr" => {
+                return Some(HeightmapFormat::Exr);
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+}
+
+pub struct Heightmap {
+    pub width: u32,
+    pub height: u32,
+    pub heights: Vec<f32> // row major, first row is region.min z
+}
+
+impl Heightmap {
+    // samples the same height the vertex shader puts on the terrain, rows are spread over all cores
+    pub fn sample(region: Region, resolution: Resolution, params: &NoiseParams, seed: &NoiseSeed) -> Result<Self, TerrainError> {
+        let (width, height) = (resolution.width as usize, resolution.height as usize);
+        let step = [(region.max[0] - region.min[0]) / (width - 1) as f32, (region.max[1] - region.min[1]) / (height - 1) as f32];
+
+        // the resolution can be any u32, so fail instead of overflowing or aborting on the allocation
+        let too_large = || TerrainError::TooLarge(format!("a {}x{} heightmap", resolution.width, resolution.height));
+        let count = width.checked_mul(height).ok_or_else(too_large)?;
+        let mut heights = Vec::new();
+        heights.try_reserve_exact(count).map_err(|_| too_large())?;
+        heights.resize(count, 0.);
+
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let rows_per_thread = height.div_ceil(threads);
+        std::thread::scope(|scope| {
+            for (chunk_index, chunk) in heights.chunks_mut(rows_per_thread * width).enumerate() {
+                scope.spawn(move || {
+                    for (i, row) in chunk.chunks_mut(width).enumerate() {
+                        let z = region.min[1] + (chunk_index * rows_per_thread + i) as f32 * step[1];
+                        for (j, value) in row.iter_mut().enumerate() {
+                            *value = noise::height(region.min[0] + j as f32 * step[0], z, 0., params, seed)[0];
+                        }
+                    }
+                });
+            }
+        });
+
+        return Ok(Self { width: resolution.width, height: resolution.height, heights });
+    }
+
+    pub fn range(&self) -> (f32, f32) {
+        return self.heights.iter().fold((f32::MAX, f32::MIN), |(min, max), &h| (min.min(h), max.max(h)));
+    }
+
+    fn normalized_u16(&self) -> Vec<u16> {
+        let (min, max) = self.range();
+        let scale = if max > min { 65535. / (max - min) } else { 0. };
+        return self.heights.iter().map(|&h| ((h - min) * scale).round() as u16).collect();
+    }
+
+    pub fn write(&self, out: impl Write, format: HeightmapFormat) -> io::Result<()> {
+        match format {
+            HeightmapFormat::Png16 => {
+                return self.write_png16(out);
+            }
+            HeightmapFormat::R16 => {
+                return self.write_r16(out);
+            }
+            HeightmapFormat::Pfm => {
+                return self.write_pfm(out);
+            }
+            HeightmapFormat::Exr => {
+                return self.write_exr(out);
+            }
+        }
+    }
+
+    fn write_png16(&self, out: impl Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(out, self.width, self.height);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Sixteen);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        // png wants big endian samples
+        let data: Vec<u8> = self.normalized_u16().iter().flat_map(|v| v.to_be_bytes()).collect();
+        writer.write_image_data(&data).map_err(io::Error::other)?;
+        return writer.finish().map_err(io::Error::other);
+    }
+
+    fn write_r16(&self, mut out: impl Write) -> io::Result<()> {
+        let data: Vec<u8> = self.normalized_u16().iter().flat_map(|v| v.to_le_bytes()).collect();
+        return out.write_all(&data);
+    }
+
+    fn write_pfm(&self, mut out: impl Write) -> io::Result<()> {
+        // negative scale means little endian, and pfm stores rows bottom to top
+        write!(out, "Pf\n{} {}\n-1.0\n", self.width, self.height)?;
+        for row in self.heights.chunks(self.width as usize).rev() {
+            for h in row {
+                out.write_all(&h.to_le_bytes())?;
+            }
+        }
+        return Ok(());
+    }
+
+    // the smallest exr there is: one float channel called Y, no compression, one scanline per chunk
+    fn write_exr(&self, mut out: impl Write) -> io::Result<()> {
+        fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+            header.extend(name.as_bytes());
+            header.push(0);
+            header.extend(kind.as_bytes());
+            header.push(0);
+            header.extend((value.len() as i32).to_le_bytes());
+            header.extend(value);
+        }
+
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "the heightmap is too large for an exr file");
+        let width = i32::try_from(self.width).map_err(|_| too_large())?;
+        let height = i32::try_from(self.height).map_err(|_| too_large())?;
+        let line_size = i32::try_from(4 * self.width as u64).map_err(|_| too_large())?;
+        let window: Vec<u8> = [0, 0, width - 1, height - 1].iter().flat_map(|v: &i32| v.to_le_bytes()).collect();
+
+        let mut channels = Vec::<u8>::new();
+        channels.extend(b"Y\0");
+        channels.extend(2i32.to_le_bytes()); // pixel type float
+        channels.extend([0, 0, 0, 0]); // pLinear and reserved
+        channels.extend(1i32.to_le_bytes()); // x sampling
+        channels.extend(1i32.to_le_bytes()); // y sampling
+        channels.push(0);
+
+        let mut header = Vec::<u8>::new();
+        header.extend(20000630i32.to_le_bytes()); // magic
+        header.extend(2i32.to_le_bytes()); // version 2, single part scanline file
+        attribute(&mut header, "channels", "chlist", &channels);
+        attribute(&mut header, "compression", "compression", &[0]);
+        attribute(&mut header, "dataWindow", "box2i", &window);
+        attribute(&mut header, "displayWindow", "box2i", &window);
+        attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+        attribute(&mut header, "pixelAspectRatio", "float", &1f32.to_le_bytes());
+        attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+        attribute(&mut header, "screenWindowWidth", "float", &1f32.to_le_bytes());
+        header.push(0);
+
+        // offset table, then every scanline as y, byte count, pixels
+        let first_line = header.len() as u64 + 8 * self.height as u64;
+        for y in 0..self.height as u64 {
+            header.extend((first_line + y * (8 + line_size as u64)).to_le_bytes());
+        }
+        out.write_all(&header)?;
+
+        for (y, row) in self.heights.chunks(self.width as usize).enumerate() {
+            out.write_all(&(y as i32).to_le_bytes())?;
+            out.write_all(&line_size.to_le_bytes())?;
+            for h in row {
+                out.write_all(&h.to_le_bytes())?;
+            }
+        }
+        return Ok(());
+    }
+}
+
+pub fn run(settings: &Settings, args: &HeightmapArgs) -> Result<(), TerrainError> {
+    let format = match args.format.or_else(|| HeightmapFormat::from_path(&args.output)) {
+        Some(format) => format,
+        None => {
+            return Err(TerrainError::UnknownFormat(args.output.clone()));
+        }
+    };
+
+    // before sampling, which can take a while at the sizes where this matters
+    if !format.supports(args.resolution) {
+        return Err(TerrainError::TooLarge(format!("a {}x{} {} heightmap", args.resolution.width, args.resolution.height, format!("{:?}", format).to_lowercase())));
+    }
+
+    let seed = NoiseSeed::from_seed(settings.seed);
+    let heightmap = Heightmap::sample(args.region, args.resolution, &settings.noise, &seed)?;
+
+    let io_error = |err| TerrainError::Io { path: args.output.clone(), err };
+    let mut out = BufWriter::new(File::create(&args.output).map_err(io_error)?);
+    heightmap.write(&mut out, format).map_err(io_error)?;
+    out.flush().map_err(io_error)?;
+
+    let (min, max) = heightmap.range();
+    println!("Wrote {}x{} heightmap to {} (heights {} to {})", heightmap.width, heightmap.height, args.output.display(), min, max);
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // rows are z, so the first row is the one at region.min z
+    fn heightmap() -> Heightmap {
+        return Heightmap { width: 2, height: 2, heights: vec![0., 0.25, 0.5, 1.] };
+    }
+
+    #[test]
+    fn parses_regions() {
+        assert_eq!("3,-1,-2,4".parse::<Region>(), Ok(Region { min: [-2., -1.], max: [3., 4.] }));
+        assert!("1,2,3".parse::<Region>().is_err());
+        assert!("1,2,3,4,5".parse::<Region>().is_err());
+        assert!("1,2,a,4".parse::<Region>().is_err());
+        assert!("1,2,1,4".parse::<Region>().is_err());
+        assert!("".parse::<Region>().is_err());
+        assert!("nan,0,1,1".parse::<Region>().is_err());
+        assert!("0,0,inf,1".parse::<Region>().is_err());
+    }
+
+    #[test]
+    fn parses_resolutions() {
+        assert_eq!("512".parse::<Resolution>(), Ok(Resolution { width: 512, height: 512 }));
+        assert_eq!("1024x2".parse::<Resolution>(), Ok(Resolution { width: 1024, height: 2 }));
+        assert!("1".parse::<Resolution>().is_err());
+        assert!("2x1".parse::<Resolution>().is_err());
+        assert!("x".parse::<Resolution>().is_err());
+        assert!("-4".parse::<Resolution>().is_err());
+        assert!("4x4x4".parse::<Resolution>().is_err());
+    }
+
+    #[test]
+    fn huge_resolutions_are_errors() {
+        let region = Region { min: [0., 0.], max: [1., 1.] };
+        let resolution = Resolution { width: u32::MAX, height: u32::MAX };
+        assert!(Heightmap::sample(region, resolution, &NoiseParams::default(), &NoiseSeed::from_seed(0)).is_err());
+        assert!(!HeightmapFormat::Exr.supports(Resolution { width: 1 << 29, height: 2 }));
+        assert!(HeightmapFormat::Exr.supports(Resolution { width: (1 << 29) - 1, height: 2 }));
+    }
+
+    #[test]
+    fn r16_is_normalized_little_endian() {
+        let mut out = Vec::new();
+        heightmap().write(&mut out, HeightmapFormat::R16).unwrap();
+        assert_eq!(out, [0x00, 0x00, 0x00, 0x40, 0x00, 0x80, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn png16_is_big_endian_grayscale() {
+        let mut out = Vec::new();
+        heightmap().write(&mut out, HeightmapFormat::Png16).unwrap();
+        let mut reader = png::Decoder::new(out.as_slice()).read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height, info.color_type, info.bit_depth), (2, 2, png::ColorType::Grayscale, png::BitDepth::Sixteen));
+        assert_eq!(data, [0x00, 0x00, 0x40, 0x00, 0x80, 0x00, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn pfm_rows_go_bottom_up() {
+        let mut out = Vec::new();
+        heightmap().write(&mut out, HeightmapFormat::Pfm).unwrap();
+        let header = b"Pf\n2 2\n-1.0\n";
+        assert_eq!(&out[..header.len()], header);
+        let values: Vec<f32> = out[header.len()..].chunks(4).map(|b| f32::from_le_bytes(b.try_into().unwrap())).collect();
+        assert_eq!(values, [0.5, 1., 0., 0.25]);
+    }
+
+    #[test]
+    fn exr_offsets_point_at_the_scanlines() {
+        let mut out = Vec::new();
+        heightmap().write(&mut out, HeightmapFormat::Exr).unwrap();
+        let int = |at: usize| i32::from_le_bytes(out[at..at + 4].try_into().unwrap());
+        assert_eq!((int(0), int(4)), (20000630, 2));
+
+        // the header ends with an empty attribute name, right before the offset table
+        let table = out.len() - 2 * (8 + 2 * 4) - 2 * 8;
+        assert_eq!(out[table - 1], 0);
+        let channels = b"channels\0chlist\0";
+        assert_eq!(&out[8..8 + channels.len()], channels);
+
+        for y in 0..2 {
+            let offset = u64::from_le_bytes(out[table + 8 * y..table + 8 * y + 8].try_into().unwrap()) as usize;
+            assert_eq!((int(offset), int(offset + 4)), (y as i32, 8));
+            let first = f32::from_le_bytes(out[offset + 8..offset + 12].try_into().unwrap());
+            assert_eq!(first, [0., 0.5][y]);
+        }
+    }
+}
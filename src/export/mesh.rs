@@ -0,0 +1,279 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use crate::cli::MeshArgs;
+use crate::error::TerrainError;
+use crate::export::heightmap::{Heightmap, Region, Resolution};
+use crate::settings::Settings;
+use crate::terrain::noise::{NoiseParams, NoiseSeed};
+use crate::vertex_data;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum MeshFormat {
+    Obj, // wavefront, text
+    Ply, // binary little endian
+    Glb // binary gltf 2.0
+}
+
+impl MeshFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "obj" => {
+                return Some(MeshFormat::Obj);
+            }
+            "ply" => {
+                return Some(MeshFormat::Ply);
+            }
+            "glb" => {
+                return Some(MeshFormat::Glb);
+            }
+            _ => {
+                return None;
+            }
+        }
+    }
+}
+
+// the terrain plane displaced on the cpu, y up like in the renderer
+pub struct TerrainMesh {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Vec<[f32; 3]>,
+    pub indices: Vec<u32>
+}
+
+impl TerrainMesh {
+    // resolution is in vertices, so 2x2 is a single quad
+    pub fn generate(region: Region, resolution: Resolution, height_scale: f32, params: &NoiseParams, seed: &NoiseSeed) -> Result<Self, TerrainError> {
+        // indices are u32, so every vertex has to be addressable with one
+        if resolution.width as u64 * resolution.height as u64 > u32::MAX as u64 {
+            return Err(TerrainError::TooLarge(format!("a {}x{} mesh", resolution.width, resolution.height)));
+        }
+
+        let heightmap = Heightmap::sample(region, resolution, params, seed)?;
+        let (width, height) = (resolution.width as usize, resolution.height as usize);
+        let step = [(region.max[0] - region.min[0]) / (width - 1) as f32, (region.max[1] - region.min[1]) / (height - 1) as f32];
+        let h = |x: usize, z: usize| heightmap.heights[z * width + x] * height_scale;
+
+        let mut positions = Vec::with_capacity(width * height);
+        let mut normals = Vec::with_capacity(width * height);
+        for z in 0..height {
+            for x in 0..width {
+                positions.push([region.min[0] + x as f32 * step[0], h(x, z), region.min[1] + z as f32 * step[1]]);
+
+                // central differences, one sided at the border
+                let (x0, x1) = (x.saturating_sub(1), (x + 1).min(width - 1));
+                let (z0, z1) = (z.saturating_sub(1), (z + 1).min(height - 1));
+                let dx = (h(x1, z) - h(x0, z)) / ((x1 - x0) as f32 * step[0]);
+                let dz = (h(x, z1) - h(x, z0)) / ((z1 - z0) as f32 * step[1]);
+                let length = (dx * dx + 1. + dz * dz).sqrt();
+                normals.push([-dx / length, 1. / length, -dz / length]);
+            }
+        }
+
+        let indices = vertex_data::grid_indices(resolution.width - 1, resolution.height - 1);
+        return Ok(Self { positions, normals, indices });
+    }
+
+    pub fn write(&self, out: impl Write, format: MeshFormat) -> io::Result<()> {
+        match format {
+            MeshFormat::Obj => {
+                return self.write_obj(out);
+            }
+            MeshFormat::Ply => {
+                return self.write_ply(out);
+            }
+            MeshFormat::Glb => {
+                return self.write_glb(out);
+            }
+        }
+    }
+
+    fn write_obj(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "# terrain-generator")?;
+        for p in &self.positions {
+            writeln!(out, "v {} {} {}", p[0], p[1], p[2])?;
+        }
+        for n in &self.normals {
+            writeln!(out, "vn {} {} {}", n[0], n[1], n[2])?;
+        }
+        // obj indices start at 1
+        for triangle in self.indices.chunks(3) {
+            let [a, b, c] = [triangle[0] + 1, triangle[1] + 1, triangle[2] + 1];
+            writeln!(out, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c)?;
+        }
+        return Ok(());
+    }
+
+    fn write_ply(&self, mut out: impl Write) -> io::Result<()> {
+        write!(out, "ply\nformat binary_little_endian 1.0\ncomment terrain-generator\n")?;
+        write!(out, "element vertex {}\nproperty float x\nproperty float y\nproperty float z\nproperty float nx\nproperty float ny\nproperty float nz\n", self.positions.len())?;
+        write!(out, "element face {}\nproperty list uchar uint vertex_indices\nend_header\n", self.indices.len() / 3)?;
+        for (p, n) in self.positions.iter().zip(&self.normals) {
+            for v in p.iter().chain(n) {
+                out.write_all(&v.to_le_bytes())?;
+            }
+        }
+        for triangle in self.indices.chunks(3) {
+            out.write_all(&[3])?;
+            for i in triangle {
+                out.write_all(&i.to_le_bytes())?;
+            }
+        }
+        return Ok(());
+    }
+
+    // one mesh, one primitive, everything in a single binary buffer: positions, normals, indices
+    fn write_glb(&self, mut out: impl Write) -> io::Result<()> {
+        let mut bin = Vec::<u8>::new();
+        bin.extend(self.positions.iter().flatten().flat_map(|v| v.to_le_bytes()));
+        bin.extend(self.normals.iter().flatten().flat_map(|v| v.to_le_bytes()));
+        bin.extend(self.indices.iter().flat_map(|i| i.to_le_bytes()));
+        let vertex_bytes = self.positions.len() * 12;
+        let index_bytes = self.indices.len() * 4;
+
+        let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+        for p in &self.positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(p[axis]);
+                max[axis] = max[axis].max(p[axis]);
+            }
+        }
+
+        let mut json = format!(concat!(
+            r#"{{"asset":{{"version":"2.0","generator":"terrain-generator"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0,"name":"terrain"}}],"#,
+            r#""meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1}},"indices":2,"mode":4}}]}}],"#,
+            r#""accessors":["#,
+            r#"{{"bufferView":0,"componentType":5126,"count":{vertices},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}},"#,
+            r#"{{"bufferView":1,"componentType":5126,"count":{vertices},"type":"VEC3"}},"#,
+            r#"{{"bufferView":2,"componentType":5125,"count":{indices},"type":"SCALAR"}}],"#,
+            r#""bufferViews":["#,
+            r#"{{"buffer":0,"byteOffset":0,"byteLength":{vertex_bytes},"target":34962}},"#,
+            r#"{{"buffer":0,"byteOffset":{vertex_bytes},"byteLength":{vertex_bytes},"target":34962}},"#,
+            r#"{{"buffer":0,"byteOffset":{index_offset},"byteLength":{index_bytes},"target":34963}}],"#,
+            r#""buffers":[{{"byteLength":{buffer_bytes}}}]}}"#),
+            min[0], min[1], min[2], max[0], max[1], max[2],
+            vertices = self.positions.len(), indices = self.indices.len(), vertex_bytes = vertex_bytes,
+            index_offset = 2 * vertex_bytes, index_bytes = index_bytes, buffer_bytes = bin.len());
+
+        // both chunks have to be 4 byte aligned, the buffer only holds 4 byte values anyway and json is padded with spaces
+        while !json.len().is_multiple_of(4) {
+            json.push(' ');
+        }
+
+        // all lengths in the container are u32, which caps a glb at 4gb
+        let length = |bytes: usize| u32::try_from(bytes).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "the mesh is too large for a glb file, use obj or ply"));
+        let total = length(12 + 8 + json.len() + 8 + bin.len())?;
+        out.write_all(b"glTF")?;
+        out.write_all(&2u32.to_le_bytes())?;
+        out.write_all(&total.to_le_bytes())?;
+        out.write_all(&length(json.len())?.to_le_bytes())?;
+        out.write_all(b"JSON")?;
+        out.write_all(json.as_bytes())?;
+        out.write_all(&length(bin.len())?.to_le_bytes())?;
+        out.write_all(b"BIN\0")?;
+        out.write_all(&bin)?;
+        return Ok(());
+    }
+}
+
+pub fn run(settings: &Settings, args: &MeshArgs) -> Result<(), TerrainError> {
+    let format = match args.format.or_else(|| MeshFormat::from_path(&args.output)) {
+        Some(format) => format,
+        None => {
+            return Err(TerrainError::UnknownFormat(args.output.clone()));
+        }
+    };
+
+    let seed = NoiseSeed::from_seed(settings.seed);
+    let mesh = TerrainMesh::generate(args.region, args.resolution, args.height_scale, &settings.noise, &seed)?;
+
+    let io_error = |err| TerrainError::Io { path: args.output.clone(), err };
+    let mut out = BufWriter::new(File::create(&args.output).map_err(io_error)?);
+    mesh.write(&mut out, format).map_err(io_error)?;
+    out.flush().map_err(io_error)?;
+
+    println!("Wrote {} vertices and {} triangles to {}", mesh.positions.len(), mesh.indices.len() / 3, args.output.display());
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh() -> TerrainMesh {
+        let settings = Settings::default();
+        let region = Region { min: [-1., 2.], max: [1., 4.] };
+        let resolution = Resolution { width: 2, height: 2 };
+        return TerrainMesh::generate(region, resolution, 1., &settings.noise, &NoiseSeed::from_seed(settings.seed)).unwrap();
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        return u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+    }
+
+    #[test]
+    fn generates_a_single_quad() {
+        let mesh = mesh();
+        assert_eq!((mesh.positions.len(), mesh.normals.len()), (4, 4));
+        assert_eq!(mesh.indices, [0, 2, 1, 1, 2, 3]);
+        assert_eq!([mesh.positions[0][0], mesh.positions[0][2]], [-1., 2.]);
+        assert_eq!([mesh.positions[3][0], mesh.positions[3][2]], [1., 4.]);
+        for n in &mesh.normals {
+            assert!(n[1] > 0. && ((n[0] * n[0] + n[1] * n[1] + n[2] * n[2]) - 1.).abs() < 1e-5);
+        }
+
+        let too_large = Resolution { width: 70000, height: 70000 };
+        let settings = Settings::default();
+        assert!(TerrainMesh::generate(Region { min: [0., 0.], max: [1., 1.] }, too_large, 1., &settings.noise, &NoiseSeed::from_seed(settings.seed)).is_err());
+    }
+
+    #[test]
+    fn obj_counts_from_one() {
+        let mut out = Vec::new();
+        mesh().write(&mut out, MeshFormat::Obj).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let count = |prefix: &str| text.lines().filter(|line| line.starts_with(prefix)).count();
+        assert_eq!((count("v "), count("vn "), count("f ")), (4, 4, 2));
+        assert!(text.lines().any(|line| line == "f 1//1 3//3 2//2"));
+    }
+
+    #[test]
+    fn ply_header_matches_the_body() {
+        let mut out = Vec::new();
+        mesh().write(&mut out, MeshFormat::Ply).unwrap();
+        let end = b"end_header\n";
+        let body = out.windows(end.len()).position(|w| w == end).unwrap() + end.len();
+        let header = std::str::from_utf8(&out[..body]).unwrap();
+        assert!(header.contains("element vertex 4\n") && header.contains("element face 2\n"));
+        assert_eq!(header.matches("property float").count(), 6);
+
+        // six floats per vertex, then a count byte and three indices per face
+        assert_eq!(out.len() - body, 4 * 6 * 4 + 2 * (1 + 3 * 4));
+        let faces = body + 4 * 6 * 4;
+        assert_eq!(out[faces], 3);
+        assert_eq!([u32_at(&out, faces + 1), u32_at(&out, faces + 5), u32_at(&out, faces + 9)], [0, 2, 1]);
+    }
+
+    #[test]
+    fn glb_chunks_are_padded() {
+        let mut out = Vec::new();
+        mesh().write(&mut out, MeshFormat::Glb).unwrap();
+        assert_eq!(&out[0..4], b"glTF");
+        assert_eq!((u32_at(&out, 4), u32_at(&out, 8) as usize), (2, out.len()));
+
+        let json_length = u32_at(&out, 12) as usize;
+        assert_eq!(&out[16..20], b"JSON");
+        assert_eq!(json_length % 4, 0);
+        let json = std::str::from_utf8(&out[20..20 + json_length]).unwrap();
+        assert!(json.starts_with('{') && json.trim_end().ends_with('}'));
+        assert!(json.contains(r#""count":4,"type":"VEC3","min""#) && json.contains(r#""count":6,"type":"SCALAR""#));
+
+        let bin = 20 + json_length;
+        let bin_length = u32_at(&out, bin) as usize;
+        assert_eq!(&out[bin + 4..bin + 8], b"BIN\0");
+        assert_eq!((bin_length % 4, bin_length), (0, 4 * 3 * 4 * 2 + 6 * 4));
+        assert_eq!(bin + 8 + bin_length, out.len());
+        assert!(json.contains(&format!(r#""buffers":[{{"byteLength":{}}}]"#, bin_length)));
+    }
+}
@@ -0,0 +1,33 @@
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+
+// exponential height fog, thick low down and thinning out with height
+// the terrain also fades into the sky towards the end of the render distance no matter what these are
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Fog {
+    pub density: f32, // at height 0, per world unit
+    pub height_falloff: f32 // higher is fog that stays closer to the ground
+}
+
+impl Default for Fog {
+    fn default() -> Self {
+        Self { density: 0.04, height_falloff: 0.6 }
+    }
+}
+
+// the layout has to match the Fog struct in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct FogUniform {
+    density: f32,
+    height_falloff: f32,
+    end: f32, // the render distance, everything is fog from here on
+    _padding: f32
+}
+
+impl Fog {
+    pub fn uniform(&self, end: f32) -> FogUniform {
+        return FogUniform { density: self.density, height_falloff: self.height_falloff, end, _padding: 0. };
+    }
+}
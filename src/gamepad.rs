@@ -0,0 +1,124 @@
+use serde::Deserialize;
+
+// the settings are always there so terrain.toml works with any build, the gamepad itself needs the gamepad feature
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GamepadSettings {
+    pub look_speed: f32, // degrees per second with the right stick all the way over
+    pub invert_y: bool,
+    pub dead_zone: f32, // of the sticks, 0 to 1
+    pub trigger_dead_zone: f32
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self { look_speed: 120., invert_y: false, dead_zone: 0.15, trigger_dead_zone: 0.05 }
+    }
+}
+
+// round instead of per axis so diagonals aren't sticky, rescaled so it still goes smoothly from 0 to 1 past the dead zone
+#[allow(unused)] // only with the gamepad feature
+pub fn apply_dead_zone(stick: [f32; 2], dead_zone: f32) -> [f32; 2] {
+    let length = (stick[0] * stick[0] + stick[1] * stick[1]).sqrt();
+    if length <= dead_zone {
+        return [0., 0.];
+    }
+    let scale = ((length - dead_zone) / (1. - dead_zone)).min(1.) / length;
+    return [stick[0] * scale, stick[1] * scale];
+}
+
+#[cfg(feature = "gamepad")]
+pub use connected::Gamepads;
+
+#[cfg(feature = "gamepad")]
+mod connected {
+    use std::collections::HashSet;
+    use gilrs::{Axis, Button, EventType, Gilrs};
+    use crate::input::{Action, Axes, Input};
+    use super::{apply_dead_zone, GamepadSettings};
+
+    // what the buttons do, the sticks and triggers move and look
+    const BUTTONS: [(Button, Action); 8] = [
+        (Button::South, Action::Jump),
+        (Button::East, Action::Crouch),
+        (Button::North, Action::ToggleWalk),
+        (Button::LeftThumb, Action::Sprint),
+        (Button::DPadLeft, Action::TimeBack),
+        (Button::DPadRight, Action::TimeForward),
+        (Button::Start, Action::PauseTime),
+        (Button::Select, Action::Screenshot)
+    ];
+
+    fn action(button: Button) -> Option<Action> {
+        return BUTTONS.iter().find(|(bound, _)| *bound == button).map(|&(_, action)| action);
+    }
+
+    // every connected gamepad at once, whichever is touched moves the player
+    #[derive(Default)]
+    pub struct Gamepads {
+        gilrs: Option<Gilrs> // None if the platform doesn't have gamepads
+    }
+
+    impl Gamepads {
+        pub fn new() -> Self {
+            match Gilrs::new() {
+                Ok(gilrs) => {
+                    return Self { gilrs: Some(gilrs) };
+                }
+                Err(err) => {
+                    eprintln!("No gamepad support: {}", err);
+                    return Self::default();
+                }
+            }
+        }
+
+        // hands the sticks, triggers and held buttons to input, returns the actions of buttons pressed since the last call
+        pub fn poll(&mut self, input: &mut Input, settings: &GamepadSettings) -> Vec<Action> {
+            let Some(gilrs) = self.gilrs.as_mut() else {
+                return Vec::new();
+            };
+            let mut pressed = Vec::new();
+            while let Some(event) = gilrs.next_event() {
+                match event.event {
+                    EventType::ButtonPressed(button, _) => pressed.extend(action(button)),
+                    EventType::Connected => println!("Gamepad connected: {}", gilrs.gamepad(event.id).name()),
+                    EventType::Disconnected => println!("Gamepad disconnected"),
+                    _ => ()
+                }
+            }
+
+            let mut axes = Axes::default();
+            let mut held = HashSet::new();
+            for (_, gamepad) in gilrs.gamepads() {
+                let [right, forward] = apply_dead_zone([gamepad.value(Axis::LeftStickX), gamepad.value(Axis::LeftStickY)], settings.dead_zone);
+                let [yaw, pitch] = apply_dead_zone([gamepad.value(Axis::RightStickX), gamepad.value(Axis::RightStickY)], settings.dead_zone);
+                let trigger = |button: Button| {
+                    let value = gamepad.button_data(button).map_or(0., |data| data.value());
+                    return if value > settings.trigger_dead_zone { value } else { 0. };
+                };
+                axes.forward += forward;
+                axes.right += right;
+                axes.up += trigger(Button::RightTrigger2) - trigger(Button::LeftTrigger2);
+                axes.look[0] += yaw;
+                axes.look[1] += if settings.invert_y { -pitch } else { pitch };
+                held.extend(BUTTONS.iter().filter(|(button, _)| gamepad.is_pressed(*button)).map(|&(_, action)| action));
+            }
+            input.set_gamepad(axes, held);
+            return pressed;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_zone_is_round_and_rescaled() {
+        assert_eq!(apply_dead_zone([0.1, -0.1], 0.15), [0., 0.]);
+        let [x, y] = apply_dead_zone([0.6, 0.8], 0.15);
+        assert!((x - 0.6).abs() < 1e-6 && (y - 0.8).abs() < 1e-6);
+        let [x, _] = apply_dead_zone([0.575, 0.], 0.15);
+        assert!((x - 0.5).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,29 @@
+use crate::cli::Args;
+use crate::error::TerrainError;
+use crate::player::Player;
+use crate::screenshot;
+use crate::settings::Settings;
+use crate::time_of_day::TimeOfDay;
+use crate::vector::Vector3;
+use crate::wgpu_context::WgpuContext;
+
+// renders one frame from the camera given on the command line and writes it to --output
+// works without a display, with --fallback-adapter even without a gpu
+pub fn run(settings: &Settings, args: &Args) -> Result<(), TerrainError> {
+    let width = settings.window.width.unwrap_or(1280);
+    let height = settings.window.height.unwrap_or(720);
+
+    let mut player = Player::new(&settings.player);
+    let [x, y, z] = args.camera;
+    let [yaw, pitch] = args.look;
+    player.place(Vector3::new(x, y, z), Vector3::new(yaw, pitch, 0.));
+
+    let mut wgpu_ctx = WgpuContext::new_headless(width, height, settings, args.fallback_adapter)?;
+    wgpu_ctx.set_light(TimeOfDay::new(&settings.time).light(&settings.light));
+    wgpu_ctx.update(std::time::Duration::ZERO, &player);
+    let (width, height, pixels) = wgpu_ctx.capture_tiled(settings.screenshot.scale)?;
+    screenshot::save_png(&args.output, width, height, &pixels)?;
+
+    println!("Wrote {}x{} frame to {}", width, height, args.output.display());
+    return Ok(());
+}
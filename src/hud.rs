@@ -0,0 +1,39 @@
+use bytemuck::{Pod, Zeroable};
+
+// the layout has to match the Hud struct in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+pub struct HudUniform {
+    speed: f32,
+    opacity: f32, // 0 hides it
+    screen: [f32; 2] // in pixels
+}
+
+// shows the fly speed in the corner for a moment whenever it changes
+#[derive(Copy, Clone, Debug, Default)]
+pub struct SpeedIndicator {
+    speed: f32,
+    remaining: f32 // seconds until it's gone
+}
+
+impl SpeedIndicator {
+    const SHOW_TIME: f32 = 2.;
+    const FADE_TIME: f32 = 0.5; // at the end of SHOW_TIME
+
+    // None when there is no speed to show, it just fades out then
+    pub fn update(&mut self, speed: Option<f32>, dt: f32) {
+        match speed {
+            Some(speed) if speed != self.speed => {
+                self.speed = speed;
+                self.remaining = Self::SHOW_TIME;
+            }
+            _ => {
+                self.remaining = (self.remaining - dt).max(0.);
+            }
+        }
+    }
+
+    pub fn uniform(&self, screen: [f32; 2]) -> HudUniform {
+        return HudUniform { speed: self.speed, opacity: (self.remaining / Self::FADE_TIME).min(1.), screen };
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashSet;
+use serde::Deserialize;
+use winit::keyboard::KeyCode;
+
+// everything a key can be bound to
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    ToggleWalk,
+    Jump,
+    Sprint,
+    Crouch,
+    PauseTime,
+    TimeBack,
+    TimeForward,
+    Screenshot,
+    ReleaseMouse
+}
+
+impl Action {
+    pub const ALL: [Action; 15] = [
+        Action::MoveForward, Action::MoveBack, Action::MoveLeft, Action::MoveRight, Action::MoveUp, Action::MoveDown,
+        Action::ToggleWalk, Action::Jump, Action::Sprint, Action::Crouch,
+        Action::PauseTime, Action::TimeBack, Action::TimeForward,
+        Action::Screenshot, Action::ReleaseMouse
+    ];
+}
+
+// keys by where they are on the keyboard and not what they type, so the defaults are in the same place on azerty
+// and shift or caps lock don't change anything. the names are winit's KeyCode ones ("KeyW", "Space", "ShiftLeft", "ArrowUp", "F2", ...)
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    pub forward: KeyCode,
+    pub back: KeyCode,
+    pub left: KeyCode,
+    pub right: KeyCode,
+    pub up: KeyCode,
+    pub down: KeyCode,
+    pub walk: KeyCode, // switches between walking and flying
+    pub jump: KeyCode,
+    pub sprint: KeyCode, // held, also boosts flying
+    pub crouch: KeyCode, // also slows flying down
+    pub pause_time: KeyCode,
+    pub time_back: KeyCode, // held
+    pub time_forward: KeyCode,
+    pub screenshot: KeyCode,
+    pub release_mouse: KeyCode // clicking into the window grabs it again
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            forward: KeyCode::KeyW,
+            back: KeyCode::KeyS,
+            left: KeyCode::KeyA,
+            right: KeyCode::KeyD,
+            up: KeyCode::KeyE,
+            down: KeyCode::KeyQ,
+            walk: KeyCode::KeyF,
+            jump: KeyCode::Space,
+            sprint: KeyCode::ShiftLeft,
+            crouch: KeyCode::ControlLeft,
+            pause_time: KeyCode::KeyT,
+            time_back: KeyCode::BracketLeft,
+            time_forward: KeyCode::BracketRight,
+            screenshot: KeyCode::F2,
+            release_mouse: KeyCode::Escape
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> KeyCode {
+        match action {
+            Action::MoveForward => self.forward,
+            Action::MoveBack => self.back,
+            Action::MoveLeft => self.left,
+            Action::MoveRight => self.right,
+            Action::MoveUp => self.up,
+            Action::MoveDown => self.down,
+            Action::ToggleWalk => self.walk,
+            Action::Jump => self.jump,
+            Action::Sprint => self.sprint,
+            Action::Crouch => self.crouch,
+            Action::PauseTime => self.pause_time,
+            Action::TimeBack => self.time_back,
+            Action::TimeForward => self.time_forward,
+            Action::Screenshot => self.screenshot,
+            Action::ReleaseMouse => self.release_mouse
+        }
+    }
+}
+
+// analog movement from a gamepad, -1 to 1 like a pair of keys
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Axes {
+    pub forward: f32,
+    pub right: f32,
+    pub up: f32,
+    pub look: [f32; 2] // yaw and pitch, the player turns by this times the gamepad look speed
+}
+
+// which keys are down right now, asked for by action
+#[derive(Default)]
+pub struct Input {
+    held: HashSet<KeyCode>,
+    bindings: KeyBindings,
+    axes: Axes,
+    held_buttons: HashSet<Action> // gamepad buttons are bound to actions directly
+}
+
+impl Input {
+    pub fn new(bindings: &KeyBindings) -> Self {
+        return Self { bindings: bindings.clone(), ..Default::default() };
+    }
+
+    pub fn set_bindings(&mut self, bindings: &KeyBindings) {
+        self.bindings = bindings.clone();
+    }
+
+    pub fn key_event(&mut self, key: KeyCode, pressed: bool) {
+        if pressed {
+            self.held.insert(key);
+        } else {
+            self.held.remove(&key);
+        }
+    }
+
+    // when the window loses focus the key ups go somewhere else, so nothing should stay held
+    pub fn release_all(&mut self) {
+        self.held.clear();
+    }
+
+    #[allow(unused)] // only with the gamepad feature
+    pub fn set_gamepad(&mut self, axes: Axes, held_buttons: HashSet<Action>) {
+        self.axes = axes;
+        self.held_buttons = held_buttons;
+    }
+
+    pub fn held(&self, action: Action) -> bool {
+        return self.held.contains(&self.bindings.key(action)) || self.held_buttons.contains(&action);
+    }
+
+    // 1 for positive, -1 for negative, anything in between from a stick or trigger
+    pub fn axis(&self, positive: Action, negative: Action) -> f32 {
+        let analog = match positive {
+            Action::MoveForward => self.axes.forward,
+            Action::MoveRight => self.axes.right,
+            Action::MoveUp => self.axes.up,
+            _ => 0.
+        };
+        return (self.held(positive) as i32 - self.held(negative) as i32) as f32 + analog;
+    }
+
+    pub fn look(&self) -> [f32; 2] {
+        return self.axes.look;
+    }
+
+    // everything bound to a key, for the actions that happen once per press
+    pub fn actions(&self, key: KeyCode) -> Vec<Action> {
+        return Action::ALL.into_iter().filter(|&action| self.bindings.key(action) == key).collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_are_physical_keys_by_name() {
+        let bindings: KeyBindings = toml::from_str("forward = \"ArrowUp\"\njump = \"KeyW\"").unwrap();
+        let mut input = Input::new(&bindings);
+        input.key_event(KeyCode::KeyW, true);
+        assert!(input.held(Action::Jump) && !input.held(Action::MoveForward));
+        assert_eq!(input.actions(KeyCode::ArrowUp), vec![Action::MoveForward]);
+
+        input.release_all();
+        assert!(!input.held(Action::Jump));
+        assert!(toml::from_str::<KeyBindings>("forward = \"w\"").is_err());
+    }
+}
@@ -0,0 +1,31 @@
+use bytemuck::{Pod, Zeroable};
+
+// the sun, uploaded to the shader as part of the uniform buffer
+// the settings are what it looks like at noon, TimeOfDay moves it and changes the colors over the day
+// the layout has to match the Light struct in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Light {
+    #[serde(skip)]
+    pub direction: [f32; 3], // towards the sun, doesn't have to be normalized, comes from the time of day
+    pub specular: f32, // how strong the highlight is, 0 turns it off
+    pub color: [f32; 3],
+    pub shininess: f32, // higher is a smaller highlight
+    pub ambient: [f32; 3], // what shadowed slopes still get
+    #[serde(skip)]
+    pub _padding: f32
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            direction: [0.4, 0.6, 0.3],
+            specular: 0.15,
+            color: [1.0, 0.95, 0.85],
+            shininess: 24.,
+            ambient: [0.22, 0.25, 0.3],
+            _padding: 0.
+        }
+    }
+}
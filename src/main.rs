@@ -1,7 +1,12 @@
+// explicit returns are the house style
+#![allow(clippy::needless_return)]
 
-
+use std::process::ExitCode;
 use crate::app::App;
-use winit::error::EventLoopError;
+use crate::cli::{Args, Command};
+use crate::error::TerrainError;
+use crate::settings::Settings;
+use clap::Parser;
 use winit::event_loop::{ControlFlow, EventLoop};
 
 mod transforms;
@@ -11,11 +16,57 @@ mod vertex_data;
 mod player;
 mod utils;
 mod vector;
+mod cli;
+mod settings;
+mod error;
+mod terrain;
+mod light;
+mod biome;
+mod water;
+mod fog;
+mod time_of_day;
+mod shadow;
+mod character;
+mod input;
+mod gamepad;
+mod hud;
+mod export;
+mod headless;
+mod screenshot;
+
+fn run(args: Args) -> Result<(), TerrainError> {
+    let mut settings = Settings::load(&args.config)?;
+    args.apply(&mut settings);
 
-fn main() -> Result<(), EventLoopError> {
-    let event_loop = EventLoop::new().unwrap();
+    match &args.command {
+        Some(Command::ExportHeightmap(export_args)) => {
+            return export::heightmap::run(&settings, export_args);
+        }
+        Some(Command::ExportMesh(export_args)) => {
+            return export::mesh::run(&settings, export_args);
+        }
+        None => ()
+    }
+    if args.headless {
+        return headless::run(&settings, &args);
+    }
+
+    let event_loop = EventLoop::new()?;
     event_loop.set_control_flow(ControlFlow::Poll);
     let mut app = App::default();
-    app.init();
-    return event_loop.run_app(&mut app);
-}
\ No newline at end of file
+    app.init(settings, args);
+    event_loop.run_app(&mut app)?;
+    return app.take_error().map_or(Ok(()), Err);
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse()) {
+        Ok(()) => {
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return ExitCode::FAILURE;
+        }
+    }
+}
@@ -1,8 +1,13 @@
 // I've shed tears of pain while writing this just so you know
 // my lifespan probably shortened by a few years because of rust
-use std::collections::HashMap;
+use crate::{character::{CharacterController, CharacterInput}, input::{Action, Input}, settings::{PlayerSettings, WalkSettings}, terrain::ground::Ground, utils::{self, magnitude, normalize}, vector::Vector3};
 
-use crate::{utils::{self, magnitude, normalize}, vector::Vector3};
+// the camera sits this far above player_position
+pub const CAMERA_HEIGHT: f32 = 5.;
+
+// how far the mouse wheel can take the fly speed away from the configured one
+const MIN_SPEED_SCALE: f32 = 1. / 64.;
+const MAX_SPEED_SCALE: f32 = 64.;
 
 #[derive(Default)]
 pub struct Player {
@@ -11,85 +16,171 @@ pub struct Player {
     pub smooth_camera_rotation: Vector3,
     pub smooth_player_position: Vector3,
     pub speed: f32,
+    pub speed_scale: f32, // from the mouse wheel, kept when the settings change
     pub sensitivity: f32,
+    pub boost: f32,
+    pub slow: f32,
+    pub scroll_step: f32,
+    pub walk: WalkSettings,
+    pub walking: bool, // on the ground with gravity instead of flying
+    controller: CharacterController // only while walking
 }
 
 impl Player {
-    pub fn new() -> Self {
-        Self { player_position: Vector3::new(0.0, 0.0, 0.0), camera_rotation: Vector3::new(0.0, 0.0, 0.0), smooth_camera_rotation: Vector3::new(0.0, 0.0, 0.0), smooth_player_position: Vector3::new(0.0, 0.0, 0.0), speed: 2.0, sensitivity: 0.1 }
+    pub fn new(settings: &PlayerSettings) -> Self {
+        Self { player_position: Vector3::new(0.0, 0.0, 0.0), camera_rotation: Vector3::new(0.0, 0.0, 0.0), smooth_camera_rotation: Vector3::new(0.0, 0.0, 0.0), smooth_player_position: Vector3::new(0.0, 0.0, 0.0), speed: settings.speed, speed_scale: 1., sensitivity: settings.sensitivity, boost: settings.boost, slow: settings.slow, scroll_step: settings.scroll_step, walk: settings.walk.clone(), walking: false, controller: CharacterController::new(&settings.walk) }
     }
 
-    // uncomment if ever needed
-    // pub fn set_position(&mut self, position: [f32; 3]) {
-    //     self.player_position = position;
-    // }
+    // for when the settings file changes, keeps position and rotation
+    pub fn apply_settings(&mut self, settings: &PlayerSettings) {
+        self.speed = settings.speed;
+        self.sensitivity = settings.sensitivity;
+        self.boost = settings.boost;
+        self.slow = settings.slow;
+        self.scroll_step = settings.scroll_step;
+        self.walk = settings.walk.clone();
+    }
 
-    // pub fn set_camera_rotation(&mut self, rotation: [f32; 3]) {
-    //     self.camera_rotation = rotation;
-    // }
+    pub fn toggle_walking(&mut self) {
+        self.walking = !self.walking;
+        self.controller.stop();
+    }
+
+    // from player_position to the bottom of the capsule while walking
+    fn feet_offset(&self) -> Vector3 {
+        return Vector3::new(0., CAMERA_HEIGHT - self.controller.eye_height(), 0.);
+    }
 
-    fn move_player(&mut self, mut movement: Vector3, amount: f32, direction: Vector3) -> Vector3 {
-        movement.x = amount * direction.x;
-        movement.y = amount * direction.y;
-        movement.z = amount * direction.z;
-        return movement;
+    // teleports without smoothing, rotation is (yaw, pitch, roll) in degrees like camera_rotation
+    pub fn place(&mut self, position: Vector3, rotation: Vector3) {
+        self.player_position = position;
+        self.smooth_player_position = position;
+        self.camera_rotation = rotation;
+        self.smooth_camera_rotation = rotation;
     }
 
+    // exponential, so every notch feels the same whether it's slow or fast
+    pub fn scroll(&mut self, notches: f32) {
+        self.speed_scale = (self.speed_scale * self.scroll_step.powf(notches)).clamp(MIN_SPEED_SCALE, MAX_SPEED_SCALE);
+    }
+
+    // with the mouse wheel and whatever modifiers are held
+    pub fn fly_speed(&self, input: &Input) -> f32 {
+        let mut speed = self.speed * self.speed_scale;
+        if input.held(Action::Sprint) {
+            speed *= self.boost;
+        }
+        if input.held(Action::Crouch) {
+            speed *= self.slow;
+        }
+        return speed;
+    }
+
+    // mouse movement in pixels
     pub fn move_camera(&mut self, x: f32, y: f32) {
-        self.camera_rotation.x += x * self.sensitivity;
-        self.camera_rotation.y -= y * self.sensitivity;
+        self.turn(x * self.sensitivity, -y * self.sensitivity);
+    }
+
+    // yaw and pitch in degrees, pitch up is positive
+    pub fn turn(&mut self, yaw: f32, pitch: f32) {
+        self.camera_rotation.x += yaw;
+        self.camera_rotation.y += pitch;
 
-        self.camera_rotation.x = self.camera_rotation.x % 360.0;
+        self.camera_rotation.x %= 360.0;
         self.camera_rotation.y = self.camera_rotation.y.clamp(-85.0, 85.0);
     }
 
     // you will need to call this every frame
-    pub fn update(&mut self, inputs: &HashMap<u8, bool>, dt: f32) {
-        let rotation: Vector3 = self.camera_rotation;
-        let move_amount = self.speed * dt;
+    pub fn update(&mut self, input: &Input, dt: f32, ground: &Ground) {
+        // walking ignores the pitch, looking down shouldn't make you slower
+        let rotation: Vector3 = if self.walking { Vector3::new(self.camera_rotation.x, 0., self.camera_rotation.z) } else { self.camera_rotation };
+        let move_amount = if self.walking { self.walk.speed * dt } else { self.fly_speed(input) * dt };
         let mut movement : Vector3 = Vector3::new(0.0, 0.0, 0.0);
 
         self.smooth_camera_rotation = self.smooth_camera_rotation.lerp_vec(self.camera_rotation, 0.9 * dt * 10.0); // 10 is the smoothing factor (increase for roughness, decrease for smoothness)
         self.smooth_player_position = self.smooth_player_position.lerp_vec(self.player_position, 0.9 * dt * 10.0);
 
-        // these must be in this order if you move a and d after the rest shit will unexpectedly break.
-        if *inputs.get(&('a' as u8)).unwrap_or(&false) {
-            let direction: Vector3 = utils::rotation_to_direction(Vector3::new(rotation.x + 270., rotation.y, rotation.z));
-            movement += self.move_player(movement, move_amount, direction);
-            movement.y = 0.0;
-        }
-        if *inputs.get(&('d' as u8)).unwrap_or(&false) {
-            let direction: Vector3 = utils::rotation_to_direction(Vector3::new(rotation.x + 90., rotation.y, rotation.z));
-            movement += self.move_player(movement, move_amount, direction);
-            movement.y = 0.0;
-        }
-        if *inputs.get(&('w' as u8)).unwrap_or(&false) {
-            let direction: Vector3 = utils::rotation_to_direction(rotation);
-            movement += self.move_player(movement, move_amount, direction);
-        }
-        if *inputs.get(&('s' as u8)).unwrap_or(&false) {
-            let direction: Vector3 = utils::rotation_to_direction(Vector3::new(rotation.x + 180., -rotation.y, rotation.z));
-            movement += self.move_player(movement, move_amount, direction);
-        }
-        if *inputs.get(&('e' as u8)).unwrap_or(&false) {
-            let direction: Vector3 = utils::rotation_to_direction(Vector3::new(rotation.x, rotation.y + 90., rotation.z));
-            movement += self.move_player(movement, move_amount, direction);
-        }
-        if *inputs.get(&('q' as u8)).unwrap_or(&false) {
-            let direction: Vector3 = utils::rotation_to_direction(Vector3::new(rotation.x, rotation.y - 90., rotation.z));
-            movement += self.move_player(movement, move_amount, direction);
-        }
-        
+        // keys give -1, 0 or 1 per axis, a gamepad anything in between
+        let sideways = input.axis(Action::MoveRight, Action::MoveLeft);
+        let forward = input.axis(Action::MoveForward, Action::MoveBack);
+        let up = if self.walking { 0. } else { input.axis(Action::MoveUp, Action::MoveDown) };
+
+        // strafing stays level, forward follows the pitch and up is up from the camera
+        let mut right_direction: Vector3 = utils::rotation_to_direction(Vector3::new(rotation.x + 90., rotation.y, rotation.z));
+        right_direction.y = 0.0;
+        let forward_direction: Vector3 = utils::rotation_to_direction(rotation);
+        let up_direction: Vector3 = utils::rotation_to_direction(Vector3::new(rotation.x, rotation.y + 90., rotation.z));
+        movement += right_direction * (sideways * move_amount);
+        movement += forward_direction * (forward * move_amount);
+        movement += up_direction * (up * move_amount);
+
         if magnitude(movement) > move_amount {
             movement = normalize(movement);
             movement *= move_amount;
         }
         movement.x = -movement.x;
-        self.player_position += movement;
+
+        if !self.walking {
+            self.player_position += movement;
+            return;
+        }
+        // the controller has momentum, so it only gets the direction
+        let input = CharacterInput {
+            direction: if move_amount > 0. { movement / move_amount } else { movement },
+            jump: input.held(Action::Jump),
+            sprint: input.held(Action::Sprint),
+            crouch: input.held(Action::Crouch)
+        };
+        let feet = self.controller.update(self.player_position + self.feet_offset(), input, dt, ground, &self.walk);
+        self.player_position = feet - self.feet_offset();
     }
 
     pub fn get_relative_position(&self, position: Vector3) -> Vector3 {
         let new_position: Vector3 = position - self.smooth_player_position;
         return new_position;
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::Settings;
+    use winit::keyboard::KeyCode;
+
+    #[test]
+    fn walking_falls_to_the_ground_and_jumps() {
+        let settings = Settings::default();
+        let ground = Ground::new(&settings);
+        let mut player = Player::new(&settings.player);
+        let flat = crate::character::tests::find_slope(&ground, |steepness| steepness < 0.1);
+        player.place(Vector3::new(flat.x, 2., flat.z), Vector3::new(0., 0., 0.));
+        player.toggle_walking();
+
+        let mut input = Input::new(&settings.keys);
+        for _ in 0..300 {
+            player.update(&input, 1. / 60., &ground);
+        }
+        // the capsule rests on the highest ground under it, which can be a bit higher than right in the middle
+        let eyes = player.player_position.y + CAMERA_HEIGHT;
+        let above = eyes - flat.y - settings.player.walk.eye_height;
+        assert!(above > -1e-5 && above < settings.player.walk.radius);
+
+        input.key_event(KeyCode::Space, true);
+        player.update(&input, 1. / 60., &ground);
+        assert!(player.player_position.y + CAMERA_HEIGHT > eyes);
+    }
+
+    #[test]
+    fn scrolling_scales_the_fly_speed_exponentially() {
+        let settings = Settings::default();
+        let mut player = Player::new(&settings.player);
+        let mut input = Input::new(&settings.keys);
+        player.scroll(2.);
+        player.scroll(-1.);
+        assert!((player.fly_speed(&input) - settings.player.speed * settings.player.scroll_step).abs() < 1e-5);
+
+        player.scroll(1000.);
+        assert_eq!(player.fly_speed(&input), settings.player.speed * MAX_SPEED_SCALE);
+        input.key_event(KeyCode::ShiftLeft, true);
+        assert_eq!(player.fly_speed(&input), settings.player.speed * MAX_SPEED_SCALE * settings.player.boost);
+    }
+}
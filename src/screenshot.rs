@@ -0,0 +1,44 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::error::TerrainError;
+
+// rgba8, rows top to bottom, like WgpuContext::capture returns them
+pub fn save_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> Result<(), TerrainError> {
+    let io_error = |err| TerrainError::Io { path: path.to_path_buf(), err };
+    let out = BufWriter::new(File::create(path).map_err(io_error)?);
+
+    let mut encoder = png::Encoder::new(out, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|err| io_error(std::io::Error::other(err)))?;
+    writer.write_image_data(pixels).map_err(|err| io_error(std::io::Error::other(err)))?;
+    return writer.finish().map_err(|err| io_error(std::io::Error::other(err)));
+}
+
+// screenshot-2024-08-30_18-04-09.png (utc) in directory, with a number appended if that already exists
+pub fn timestamped_path(directory: &Path) -> PathBuf {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // days since 1970 to a date, from http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let name = format!("screenshot-{:04}-{:02}-{:02}_{:02}-{:02}-{:02}", year, month, day, time / 3600, time / 60 % 60, time % 60);
+    let mut path = directory.join(format!("{}.png", name));
+    let mut counter = 2;
+    while path.exists() {
+        path = directory.join(format!("{}-{}.png", name, counter));
+        counter += 1;
+    }
+    return path;
+}
@@ -0,0 +1,287 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use serde::Deserialize;
+use crate::error::TerrainError;
+use crate::biome::Biomes;
+use crate::fog::Fog;
+use crate::gamepad::GamepadSettings;
+use crate::input::KeyBindings;
+use crate::light::Light;
+use crate::shadow::Shadows;
+use crate::water::Water;
+use crate::terrain::noise::NoiseParams;
+use crate::vertex_data::{Quality, RenderDistance};
+
+// everything the user can change without recompiling
+// loaded from terrain.toml (see the one in the repo root for all the keys), command line flags win over the file
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub quality: Quality,
+    pub render_distance: RenderDistance,
+    pub seed: u64,
+    pub fov: f32, // vertical, in degrees
+    pub window: WindowSettings,
+    pub noise: NoiseParams,
+    pub light: Light,
+    pub biomes: Biomes,
+    pub water: Water,
+    pub fog: Fog,
+    pub time: TimeSettings,
+    pub shadows: Shadows,
+    pub player: PlayerSettings,
+    pub keys: KeyBindings,
+    pub gamepad: GamepadSettings,
+    pub screenshot: ScreenshotSettings
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            quality: Quality::default(),
+            render_distance: RenderDistance::default(),
+            seed: 0,
+            fov: 72.,
+            window: WindowSettings::default(),
+            noise: NoiseParams::default(),
+            light: Light::default(),
+            biomes: Biomes::default(),
+            water: Water::default(),
+            fog: Fog::default(),
+            time: TimeSettings::default(),
+            shadows: Shadows::default(),
+            player: PlayerSettings::default(),
+            keys: KeyBindings::default(),
+            gamepad: GamepadSettings::default(),
+            screenshot: ScreenshotSettings::default()
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WindowSettings {
+    // None leaves the size up to the os
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fullscreen: bool
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeSettings {
+    pub start_hour: f32, // 0 to 24, only read at startup
+    pub day_length: f32, // real seconds for a whole day, 0 stops the clock
+    pub paused: bool
+}
+
+impl Default for TimeSettings {
+    fn default() -> Self {
+        Self { start_hour: 10., day_length: 600., paused: false }
+    }
+}
+
+// F2 in the window
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScreenshotSettings {
+    pub directory: PathBuf,
+    pub scale: u32 // 2 renders at twice the window resolution and so on, also applies to --headless
+}
+
+impl ScreenshotSettings {
+    // 8 times a 1080p window is about 130 megapixels
+    pub const MAX_SCALE: u32 = 8;
+    // the whole image is held in memory as rgba8 while it's stitched and encoded, this keeps that at 1gb
+    pub const MAX_PIXELS: u64 = 256 * 1024 * 1024;
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        Self { directory: PathBuf::from("screenshots"), scale: 1 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlayerSettings {
+    pub speed: f32, // flying, the mouse wheel changes it while playing
+    pub sensitivity: f32,
+    pub boost: f32, // speed times this while sprint is held
+    pub slow: f32, // and this while crouch is
+    pub scroll_step: f32, // each notch of the mouse wheel multiplies the speed by this
+    pub walk: WalkSettings
+}
+
+impl Default for PlayerSettings {
+    fn default() -> Self {
+        Self { speed: 2.0, sensitivity: 0.1, boost: 4., slow: 0.25, scroll_step: 1.25, walk: WalkSettings::default() }
+    }
+}
+
+// walking mode, in world units (the mountains are about 1 high) and seconds
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WalkSettings {
+    pub speed: f32,
+    pub sprint_speed: f32,
+    pub crouch_speed: f32,
+    pub acceleration: f32, // how fast the player gets up to speed
+    pub friction: f32, // how fast they stop when nothing is pressed
+    pub air_control: f32, // part of the acceleration that's left while in the air
+    pub jump_speed: f32,
+    pub gravity: f32,
+    pub eye_height: f32, // the camera above the ground
+    pub crouch_eye_height: f32,
+    pub radius: f32, // of the capsule the player is
+    pub max_slope: f32, // in degrees, anything steeper can't be walked up and slides the player down
+    pub step_height: f32, // how much higher than max_slope allows the ground can get in one move before it blocks it
+    pub snap_distance: f32 // how far the ground can fall away before the player leaves it
+}
+
+impl Default for WalkSettings {
+    fn default() -> Self {
+        Self {
+            speed: 0.4,
+            sprint_speed: 0.8,
+            crouch_speed: 0.15,
+            acceleration: 4.,
+            friction: 6.,
+            air_control: 0.2,
+            jump_speed: 0.8,
+            gravity: 3.,
+            eye_height: 0.15,
+            crouch_eye_height: 0.1,
+            radius: 0.04,
+            max_slope: 60.,
+            step_height: 0.02,
+            snap_distance: 0.02
+        }
+    }
+}
+
+impl Settings {
+    pub const DEFAULT_PATH: &'static str = "terrain.toml";
+
+    // a missing file is fine and just means defaults, a broken one is not
+    pub fn load(path: &Path) -> Result<Self, TerrainError> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let error = |reason: String| TerrainError::Settings { path: path.to_path_buf(), reason };
+        let text = std::fs::read_to_string(path).map_err(|err| error(err.to_string()))?;
+        let settings: Self = toml::from_str(&text).map_err(|err| error(err.to_string()))?;
+        settings.validate().map_err(error)?;
+        return Ok(settings);
+    }
+
+    // what serde can't check on its own, anything that would panic or stall the renderer later
+    // the float checks are written so nan fails them too
+    fn validate(&self) -> Result<(), String> {
+        if !(self.fov > 0. && self.fov < 180.) {
+            return Err(format!("fov has to be between 0 and 180 degrees, got {}", self.fov));
+        }
+        if self.noise.octave_count > NoiseParams::MAX_OCTAVES {
+            return Err(format!("noise.octave_count can be at most {}, got {}", NoiseParams::MAX_OCTAVES, self.noise.octave_count));
+        }
+        if self.noise.warps > NoiseParams::MAX_WARPS {
+            return Err(format!("noise.warps can be at most {}, got {}", NoiseParams::MAX_WARPS, self.noise.warps));
+        }
+        if self.player.scroll_step.is_nan() || self.player.scroll_step <= 0. {
+            return Err(format!("player.scroll_step has to be above 0, got {}", self.player.scroll_step));
+        }
+        if !(0.0..).contains(&self.player.walk.radius) {
+            return Err(format!("player.walk.radius can't be negative, got {}", self.player.walk.radius));
+        }
+        if !(1..=Shadows::MAX_RESOLUTION).contains(&self.shadows.resolution) {
+            return Err(format!("shadows.resolution has to be between 1 and {}, got {}", Shadows::MAX_RESOLUTION, self.shadows.resolution));
+        }
+        if !(1..=ScreenshotSettings::MAX_SCALE).contains(&self.screenshot.scale) {
+            return Err(format!("screenshot.scale has to be between 1 and {}, got {}", ScreenshotSettings::MAX_SCALE, self.screenshot.scale));
+        }
+        return Ok(());
+    }
+}
+
+// polls the modification time of the settings file, cheap enough to call every frame
+#[derive(Default)]
+pub struct SettingsWatcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    last_check: Option<Instant>
+}
+
+impl SettingsWatcher {
+    const CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+    pub fn new(path: PathBuf) -> Self {
+        let modified = Self::modified_time(&path);
+        return Self { path, modified, last_check: None };
+    }
+
+    fn modified_time(path: &Path) -> Option<SystemTime> {
+        return std::fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    }
+
+    pub fn path(&self) -> &Path {
+        return &self.path;
+    }
+
+    // true once per change of the file
+    pub fn changed(&mut self) -> bool {
+        if self.last_check.is_some_and(|last_check| last_check.elapsed() < Self::CHECK_INTERVAL) {
+            return false;
+        }
+        self.last_check = Some(Instant::now());
+
+        let modified = Self::modified_time(&self.path);
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        return true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_file_matches_defaults() {
+        let settings: Settings = toml::from_str(include_str!("../terrain.toml")).unwrap();
+        assert_eq!(settings, Settings::default());
+    }
+
+    #[test]
+    fn numbers_work_for_quality_and_render_distance() {
+        let settings: Settings = toml::from_str("quality = \"4\"\nrender_distance = \"8x\"").unwrap();
+        assert_eq!(settings.quality, Quality::Ultra);
+        assert_eq!(settings.render_distance, RenderDistance::Octuple);
+    }
+
+    #[test]
+    fn screenshot_scale_is_validated() {
+        assert!(Settings::default().validate().is_ok());
+        for scale in [0, ScreenshotSettings::MAX_SCALE + 1] {
+            let settings: Settings = toml::from_str(&format!("[screenshot]\nscale = {}", scale)).unwrap();
+            assert!(settings.validate().is_err());
+        }
+    }
+
+    #[test]
+    fn values_that_break_rendering_are_rejected() {
+        for text in ["fov = 0", "fov = 180", "fov = nan", "noise.octave_count = 17", "noise.warps = 9", "player.scroll_step = 0",
+                     "player.walk.radius = -0.1", "player.walk.radius = nan", "player.scroll_step = nan", "shadows.resolution = 0", "shadows.resolution = 100000"] {
+            let settings: Settings = toml::from_str(text).unwrap();
+            assert!(settings.validate().is_err(), "{}", text);
+        }
+        let settings: Settings = toml::from_str("fov = 179\nnoise.octave_count = 16\nplayer.walk.radius = 0").unwrap();
+        assert!(settings.validate().is_ok());
+    }
+
+    #[test]
+    fn unknown_keys_are_errors() {
+        assert!(toml::from_str::<Settings>("qualty = \"mid\"").is_err());
+    }
+}
@@ -1,16 +1,102 @@
 
+// has to match NoiseParams in src/terrain/noise.rs
+struct NoiseParams {
+    amplitude: f32,
+    frequency: f32,
+    octave_count: u32,
+    persistence: f32,
+    lacunarity: f32,
+    warps: u32,
+    falloff: f32,
+    warp_scale: f32,
+};
+
+// has to match NoiseSeed in src/terrain/noise.rs
+struct NoiseSeed {
+    offset: vec2<f32>,
+    salt: f32,
+};
+
+// has to match Light in src/light.rs
+struct Light {
+    direction: vec3<f32>, // towards the sun
+    specular: f32,
+    color: vec3<f32>,
+    shininess: f32,
+    ambient: vec3<f32>,
+};
+
+// has to match BiomeUniform in src/biome.rs
+struct Biomes {
+    beach_height: f32,
+    snow_height: f32,
+    rock_slope: f32,
+    climate_scale: f32,
+    blend: f32,
+    colors: array<vec4<f32>, 8>, // dry and wet srgb for sand, grass, rock, snow
+};
+
+// has to match WaterUniform in src/water.rs
+struct Water {
+    level: f32,
+    time: f32,
+    foam_width: f32,
+    wave_scale: f32,
+    color: vec3<f32>, // srgb
+    wave_strength: f32,
+    absorption: vec3<f32>,
+    wave_speed: f32,
+};
+
+// has to match FogUniform in src/fog.rs
+struct Fog {
+    density: f32,
+    height_falloff: f32,
+    end: f32, // render distance
+};
+
+// has to match ShadowUniform in src/shadow.rs
+struct Shadows {
+    radii: vec3<f32>, // how far each cascade reaches from the camera
+    texel: f32, // 1 / resolution, 0 when there are no shadows
+};
+
+// has to match HudUniform in src/hud.rs
+struct Hud {
+    speed: f32,
+    opacity: f32, // 0 hides it
+    screen: vec2<f32>, // in pixels
+};
+
 struct Uniforms {
     modelMat: mat4x4<f32>,
     viewMat: mat4x4<f32>,
     projectMat: mat4x4<f32>,
-    transformForNoise: vec4<f32>,
+    noiseParams: NoiseParams,
+    noiseSeed: NoiseSeed,
+    cameraPos: vec4<f32>, // world space
+    light: Light,
+    biomes: Biomes,
+    water: Water,
+    skyMat: mat4x4<f32>, // clip space on the far plane to world space directions, see sky_matrix in wgpu_context.rs
+    fog: Fog,
+    shadowMats: array<mat4x4<f32>, 3>, // world space to each cascade, opengl style like projectMat
+    shadows: Shadows,
+    hud: Hud,
 };
 
 @binding(0) @group(0) var<uniform> uniforms : Uniforms;
 
+// one layer per cascade, rendered by vs_shadow
+@binding(0) @group(1) var shadow_map: texture_depth_2d_array;
+@binding(1) @group(1) var shadow_sampler: sampler_comparison;
+// which cascade the shadow pass is drawing, only bound there
+@binding(2) @group(1) var<uniform> cascade: u32;
+
 struct Output {
     @builtin(position) Position: vec4<f32>,
-    @location(0) vColor: vec4<f32>
+    @location(0) vNormal: vec3<f32>,
+    @location(1) vWorldPos: vec3<f32>
 };
 
 fn rand2d(pos: vec2<f32>) -> f32 {
@@ -25,10 +111,13 @@ const OPENGL2WGSL = mat4x4<f32>(
     0., 0., 0.5, 1. 
 );
 
+// the noise functions below are ported to rust in src/terrain/noise.rs, keep them in sync
 fn permute4(x: vec4f) -> vec4f { return ((x * 34. + 1.) * x) % vec4f(289.); }
 fn fade2(t: vec2f) -> vec2f { return t * t * t * (t * (t * 6. - 15.) + 10.); }
+fn fade2_derivative(t: vec2f) -> vec2f { return 30. * t * t * (t * (t - 2.) + 1.); }
 
-fn perlinNoise2(P: vec2f) -> f32 { // credit to Stefan Gustavson for this perlin implementation
+// value, d/dx and d/dy
+fn perlinNoise2(P: vec2f) -> vec3f { // credit to Stefan Gustavson for this perlin implementation
     var Pi: vec4f = floor(P.xyxy) + vec4f(0., 0., 1., 1.);
     let Pf = fract(P.xyxy) - vec4f(0., 0., 1., 1.);
     Pi = Pi % vec4f(289.); 
@@ -37,7 +126,7 @@ fn perlinNoise2(P: vec2f) -> f32 { // credit to Stefan Gustavson for this perlin
     let fx = Pf.xzxz;
     let fy = Pf.yyww;
     let i = permute4(permute4(ix) + iy);
-    var gx: vec4f = 2. * fract(i * 0.0243902439) - 1.;
+    var gx: vec4f = 2. * fract(i * 0.0243902439 + uniforms.noiseSeed.salt) - 1.; // the salt rotates which gradient a corner gets, different seed different world
     let gy = abs(gx) - 0.5;
     let tx = floor(gx + 0.5);
     gx = gx - tx;
@@ -57,67 +146,386 @@ fn perlinNoise2(P: vec2f) -> f32 { // credit to Stefan Gustavson for this perlin
     let fade_xy = fade2(Pf.xy);
     let n_x = mix(vec2f(n00, n01), vec2f(n10, n11), vec2f(fade_xy.x));
     let n_xy = mix(n_x.x, n_x.y, fade_xy.y);
-    return 2.3 * n_xy;
+
+    // the corner dot products are linear with their gradient as slope, plus the change of the fade weights
+    let dfade_xy = fade2_derivative(Pf.xy);
+    let g_xy = mix(mix(g00, g10, vec2f(fade_xy.x)), mix(g01, g11, vec2f(fade_xy.x)), vec2f(fade_xy.y));
+    let d = g_xy + dfade_xy * vec2f(mix(n10 - n00, n11 - n01, fade_xy.y), n_x.y - n_x.x);
+    return 2.3 * vec3f(n_xy, d);
+}
+
+// how much of an octave goes into the derivatives, all of it up to half of max_frequency and none from max_frequency on
+fn derivative_weight(frequency: f32, max_frequency: f32) -> f32 {
+    return clamp(2. * (1. - frequency / max_frequency), 0., 1.);
 }
 
-fn fbm(pos: vec2<f32>) -> f32 {
+// value and derivatives like perlinNoise2
+// octaves above max_frequency are left out of the derivatives (not the value), the mesh is too coarse to show them anyway
+fn fbm(pos: vec2<f32>, max_frequency: f32) -> vec3<f32> {
 
-    // can be fucked around with
-    var amplitude: f32 = 1.25;
-    var frequency: f32 = 0.7;
-    var octave_count: u32 = 7;
-    var persistence: f32 = 0.45;
-    var lacunarity: f32 = 2.5;
+    // can be fucked around with (at runtime now, see NoiseParams)
+    var amplitude: f32 = uniforms.noiseParams.amplitude;
+    var frequency: f32 = uniforms.noiseParams.frequency;
 
 
-    var value: f32 = 0.;
-	for (var i: u32 = 0; i < octave_count; i++) {
-		value += amplitude * perlinNoise2(vec2<f32>(pos.x * frequency, pos.y * frequency));
-		amplitude *= persistence;
-		frequency *= lacunarity;
+    var value: vec3<f32> = vec3<f32>(0.);
+	for (var i: u32 = 0; i < uniforms.noiseParams.octave_count; i++) {
+		let slope = frequency * derivative_weight(frequency, max_frequency);
+		value += amplitude * vec3<f32>(1., slope, slope) * perlinNoise2(vec2<f32>(pos.x * frequency, pos.y * frequency));
+		amplitude *= uniforms.noiseParams.persistence;
+		frequency *= uniforms.noiseParams.lacunarity;
     }
     return value;
 }
 
-fn domain_warp(pos: vec2<f32>) -> vec2<f32> {
+// a warped position and how it moves when the input moves along x and along z
+struct Warp {
+    pos: vec2<f32>,
+    ddx: vec2<f32>,
+    ddz: vec2<f32>,
+};
 
+fn domain_warp(pos: vec2<f32>, max_frequency: f32) -> Warp {
 
-    // can be fucked around with
-    var warps: u32 = 2;
-    var falloff: f32 = 0.9;
-    var scale: f32 = 0.3;
+    var scale: f32 = uniforms.noiseParams.warp_scale;
 
-    
-    var x: f32 = pos.x;
-    var y: f32 = pos.y;
-    for (var i: u32 = 0; i < warps; i++) {
-		x += scale * fbm(pos);
-		y += scale * fbm(-pos);
-		scale *= falloff;
+    var warp: Warp = Warp(pos, vec2<f32>(1., 0.), vec2<f32>(0., 1.));
+    for (var i: u32 = 0; i < uniforms.noiseParams.warps; i++) {
+		let a = fbm(pos, max_frequency);
+		let b = fbm(-pos, max_frequency); // sampled at -pos, so its derivatives flip
+		warp.pos += scale * vec2<f32>(a.x, b.x);
+		warp.ddx += scale * vec2<f32>(a.y, -b.y);
+		warp.ddz += scale * vec2<f32>(a.z, -b.z);
+		scale *= uniforms.noiseParams.falloff;
 	}
-    return vec2<f32>(x, y);
+    return warp;
+}
+
+// value, d/dx and d/dz at world position pos
+// the derivatives only have detail down to about filter_width world units, has to be more than 0
+fn noise(pos: vec2<f32>, filter_width: f32) -> vec3<f32> {
+    // a wave has to be at least two filter widths long, and noise space is half as big as the world
+    let max_frequency = 1. / filter_width;
+    let warp = domain_warp((pos + uniforms.noiseSeed.offset) * 0.5, max_frequency);
+    let value = fbm(warp.pos, max_frequency);
+    // chain rule through the warp, the 0.5 is the scale of pos above
+    return vec3<f32>(value.x, 0.5 * dot(value.yz, warp.ddx), 0.5 * dot(value.yz, warp.ddz));
+}
+
+// height, d/dx and d/dz
+fn height(pos: vec2<f32>, filter_width: f32) -> vec3<f32> {
+    let noise = noise(pos, filter_width);
+    return vec3<f32>((noise.x + 1) / 2., noise.yz / 2.);
+}
+
+fn terrain_normal(gradient: vec2<f32>) -> vec3<f32> {
+    return normalize(vec3<f32>(-gradient.x, 1., -gradient.y));
+}
+
+// slides every other vertex onto its even neighbour as the chunk gets farther away, so at the end of its lod range
+// it has the same grid as the next level and the edges between them line up (see src/terrain/lod.rs)
+// morph is quad size, morph start and morph end distance
+fn lod_morph(pos: vec2<f32>, morph: vec4<f32>) -> vec2<f32> {
+    let k = clamp((distance(pos, uniforms.cameraPos.xz) - morph.y) / (morph.z - morph.y), 0., 1.);
+    let odd = fract(round(pos / morph.x) * 0.5) * 2.;
+    return pos - odd * morph.x * k;
 }
 
-fn noise(pos: vec2<f32>) -> f32 {
-    var warped: vec2<f32> = domain_warp(pos * 0.5);
-    return fbm(warped);
+// placement is the chunk origin on x and z and its size, the grid goes from 0 to 1
+// where a vertex of the grid ends up on x and z for a chunk, the shadow pass has to get the exact same mesh
+fn chunk_position(grid: vec4<f32>, placement: vec4<f32>, morph: vec4<f32>) -> vec2<f32> {
+    return lod_morph(vec2<f32>(placement.x + grid.x * placement.z, placement.y + grid.z * placement.z), morph);
 }
 
 @vertex
-fn vs_main(@location(0) _pos: vec4<f32>) -> Output {
+fn vs_main(@location(0) grid: vec4<f32>, @location(1) placement: vec4<f32>, @location(2) morph: vec4<f32>) -> Output {
     var output: Output;
-    var pos: vec4<f32> = _pos;
-
+    let morphed = chunk_position(grid, placement, morph);
+    var pos: vec4<f32> = vec4<f32>(morphed.x, grid.y, morphed.y, grid.w);
 
-    pos = (pos + vec4<f32>(0., (noise(vec2<f32>(pos.x + uniforms.transformForNoise.x, pos.z + uniforms.transformForNoise.y)) + 1) / 2., 0., 0.));
+    // chunks are in world space, so the noise is sampled right where the vertex is
+    // the slope is only as detailed as the quads, finer octaves would just flicker
+    let height = height(pos.xz, morph.x);
+    pos = (pos + vec4<f32>(0., height.x, 0., 0.));
 
 
     output.Position = (((OPENGL2WGSL * uniforms.projectMat) * uniforms.viewMat) * uniforms.modelMat) * pos;
-    output.vColor = vec4<f32>(pos.y);
+    output.vNormal = terrain_normal(height.yz);
+    output.vWorldPos = pos.xyz;
+    return output;
+}
+
+// the terrain seen from the sun, depth only
+@vertex
+fn vs_shadow(@location(0) grid: vec4<f32>, @location(1) placement: vec4<f32>, @location(2) morph: vec4<f32>) -> @builtin(position) vec4<f32> {
+    let pos = chunk_position(grid, placement, morph);
+    let height = height(pos, morph.x);
+    return OPENGL2WGSL * uniforms.shadowMats[cascade] * vec4<f32>(pos.x, height.x, pos.y, 1.);
+}
+
+// 1 in the sun, 0 in the shadow, averaged over 3x3 texels of the closest cascade that reaches this far
+fn sun_visibility(pos: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let radii = uniforms.shadows.radii;
+    let distance = length(pos.xz - uniforms.cameraPos.xz);
+    if (uniforms.shadows.texel == 0. || distance > radii.z) {
+        return 1.;
+    }
+    let index = select(select(2u, 1u, distance <= radii.y), 0u, distance <= radii.x);
+
+    // pushed off the surface by about a texel, so the terrain doesn't shadow itself
+    let texel_size = 2. * radii[index] * uniforms.shadows.texel;
+    let clip = OPENGL2WGSL * uniforms.shadowMats[index] * vec4<f32>(pos + normal * 1.5 * texel_size, 1.);
+    let uv = clip.xy * vec2<f32>(0.5, -0.5) + 0.5;
+
+    var lit = 0.;
+    for (var x = -1; x <= 1; x++) {
+        for (var y = -1; y <= 1; y++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * uniforms.shadows.texel;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, index, clip.z);
+        }
+    }
+    return lit / 9.;
+}
+
+// moisture and temperature from 0 to 1, two slow noise fields of their own (shifted away from each other and the terrain)
+fn climate(pos: vec2<f32>, height: f32) -> vec2<f32> {
+    let p = (pos + uniforms.noiseSeed.offset) * uniforms.biomes.climate_scale;
+    let moisture = perlinNoise2(p + vec2<f32>(37.1, -11.3)).x + 0.5 * perlinNoise2(2. * p + vec2<f32>(-5.9, 71.4)).x;
+    let temperature = perlinNoise2(p + vec2<f32>(-53.7, 91.2)).x + 0.5 * perlinNoise2(2. * p + vec2<f32>(13.3, 29.8)).x;
+    // it gets colder up high
+    return clamp(0.5 + 0.4 * vec2<f32>(moisture, temperature) - vec2<f32>(0., 0.3 * (height - 0.5)), vec2<f32>(0.), vec2<f32>(1.));
+}
+
+// picks a color from a ramp in uniforms.biomes.colors, t = 0 is the first color
+fn ramp(index: u32, t: f32) -> vec3<f32> {
+    let color = mix(uniforms.biomes.colors[2u * index].rgb, uniforms.biomes.colors[2u * index + 1u].rgb, clamp(t, 0., 1.));
+    return pow(color, vec3<f32>(2.2)); // srgb to linear, the render target converts back
+}
+
+// 0 well below edge, 1 well above it
+fn above(x: f32, edge: f32, width: f32) -> f32 {
+    return smoothstep(edge - width, edge + width, x);
+}
+
+// layered from the bottom up: grass (or desert where it's hot and dry), beach (and sea floor), rock on steep slopes, snow
+fn biome_color(pos: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+    let biomes = uniforms.biomes;
+    let climate = climate(pos.xz, pos.y);
+    let moisture = climate.x;
+    let temperature = climate.y;
+    let slope = length(normal.xz) / max(normal.y, 0.001);
+
+    let desert = above(temperature, 0.6, 0.05) * (1. - above(moisture, 0.35, 0.05));
+    var color = mix(ramp(1u, moisture), ramp(0u, moisture), desert);
+    color = mix(ramp(0u, moisture), color, above(pos.y, uniforms.water.level + biomes.beach_height, biomes.blend));
+    let rock = above(slope, biomes.rock_slope, 0.3);
+    color = mix(color, ramp(2u, moisture), rock);
+    // snow doesn't stay on cliffs
+    let snow_line = biomes.snow_height - 0.2 * (temperature - 0.5);
+    color = mix(color, ramp(3u, moisture), above(pos.y, snow_line, biomes.blend) * (1. - above(slope, 2. * biomes.rock_slope, 0.3)));
+    return color;
+}
+
+@fragment
+fn fs_main(@location(0) vNormal: vec3<f32>, @location(1) vWorldPos: vec3<f32>) -> @location(0) vec4<f32> {
+    let normal = normalize(vNormal);
+    let to_sun = normalize(uniforms.light.direction);
+    let to_camera = normalize(uniforms.cameraPos.xyz - vWorldPos);
+
+    // lambert for the ground, blinn-phong for a bit of sheen
+    let diffuse = max(dot(normal, to_sun), 0.);
+    let halfway = normalize(to_sun + to_camera);
+    let specular = uniforms.light.specular * pow(max(dot(normal, halfway), 0.), uniforms.light.shininess) * step(0., dot(normal, to_sun));
+
+    let sun = sun_visibility(vWorldPos, normal);
+    let albedo = biome_color(vWorldPos, normal);
+    let color = albedo * (uniforms.light.ambient + uniforms.light.color * diffuse * sun) + uniforms.light.color * specular * sun;
+    return vec4<f32>(apply_fog(color, vWorldPos), 1.);
+}
+
+// a gradient from the horizon to the zenith that follows the sun, darker at night and red when it's low, plus a glow around it
+// no sun disk, so water can reflect it without a second highlight on top of its specular
+fn sky(direction: vec3<f32>) -> vec3<f32> {
+    let to_sun = normalize(uniforms.light.direction);
+    let day = smoothstep(-0.15, 0.25, to_sun.y);
+    let towards_sun = max(dot(direction, to_sun), 0.);
+
+    let zenith = mix(vec3<f32>(0.004, 0.006, 0.02), vec3<f32>(0.08, 0.22, 0.6), day);
+    var horizon = mix(vec3<f32>(0.02, 0.025, 0.05), vec3<f32>(0.55, 0.68, 0.85), day);
+    let sunset = 1. - smoothstep(0., 0.35, abs(to_sun.y));
+    horizon = mix(horizon, vec3<f32>(0.95, 0.45, 0.15), sunset * (0.3 + 0.7 * towards_sun));
+
+    let color = mix(horizon, zenith, 1. - pow(1. - clamp(direction.y, 0., 1.), 3.));
+    return color + uniforms.light.color * 0.25 * pow(towards_sun, 12.) * day;
+}
+
+// exponential height fog integrated from the camera to pos, and a fade into the sky at the end of the render distance
+fn apply_fog(color: vec3<f32>, pos: vec3<f32>) -> vec3<f32> {
+    let fog = uniforms.fog;
+    let camera = uniforms.cameraPos.xyz;
+    let ray = pos - camera;
+    let distance = length(ray);
+    let direction = ray / max(distance, 0.0001);
+
+    // the density is density * exp(-height_falloff * y), this is its integral along the ray
+    var thickness = fog.density * exp(-fog.height_falloff * camera.y) * distance;
+    if abs(direction.y) > 0.0001 {
+        thickness = thickness * (1. - exp(-fog.height_falloff * direction.y * distance)) / (fog.height_falloff * direction.y * distance);
+    }
+    let height_fog = 1. - exp(-thickness);
+    let edge = smoothstep(0.6 * fog.end, fog.end, length(ray.xz));
+    let amount = 1. - (1. - height_fog) * (1. - edge);
+    return mix(color, sky(direction), amount);
+}
+
+struct SkyOutput {
+    @builtin(position) Position: vec4<f32>,
+    @location(0) vClip: vec2<f32>
+};
+
+// one triangle big enough to cover the screen, at depth 1
+@vertex
+fn vs_sky(@builtin(vertex_index) index: u32) -> SkyOutput {
+    var output: SkyOutput;
+    let clip = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u)) * 2. - 1.;
+    output.Position = vec4<f32>(clip, 1., 1.);
+    output.vClip = clip;
+    return output;
+}
+
+@fragment
+fn fs_sky(@location(0) vClip: vec2<f32>) -> @location(0) vec4<f32> {
+    let far = uniforms.skyMat * vec4<f32>(vClip, 1., 1.);
+    let direction = normalize(far.xyz / far.w);
+    let to_sun = normalize(uniforms.light.direction);
+    let disk = smoothstep(0.9995, 0.9998, dot(direction, to_sun));
+    return vec4<f32>(sky(direction) + uniforms.light.color * 8. * disk, 1.);
+}
+
+struct WaterOutput {
+    @builtin(position) Position: vec4<f32>,
+    @location(0) vWorldPos: vec3<f32>,
+    @location(1) vDepth: f32 // how far below the surface the terrain is, negative where it sticks out
+};
+
+@vertex
+fn vs_water(@location(0) grid: vec4<f32>, @location(1) placement: vec4<f32>, @location(2) morph: vec4<f32>) -> WaterOutput {
+    var output: WaterOutput;
+    let pos = lod_morph(placement.xy + grid.xz * placement.z, morph);
+    let world = vec4<f32>(pos.x, uniforms.water.level, pos.y, 1.);
+
+    output.Position = (((OPENGL2WGSL * uniforms.projectMat) * uniforms.viewMat) * uniforms.modelMat) * world;
+    output.vWorldPos = world.xyz;
+    output.vDepth = uniforms.water.level - height(pos, morph.x).x;
+    return output;
+}
+
+// premultiplied alpha, what's left of the terrain behind shows through as alpha
+@fragment
+fn fs_water(@location(0) vWorldPos: vec3<f32>, @location(1) vDepth: f32) -> @location(0) vec4<f32> {
+    let water = uniforms.water;
+    let light = uniforms.light;
+    let depth = max(vDepth, 0.);
+
+    // two layers of ripples drifting in different directions
+    let p = vWorldPos.xz * water.wave_scale;
+    let drift = water.time * water.wave_speed * water.wave_scale;
+    let ripples = perlinNoise2(p + vec2<f32>(drift, 0.3 * drift)).yz + 0.5 * perlinNoise2(1.9 * p + vec2<f32>(-0.7 * drift, drift)).yz;
+    let normal = normalize(vec3<f32>(-ripples.x * water.wave_strength, 1., -ripples.y * water.wave_strength));
+
+    let to_sun = normalize(light.direction);
+    let to_camera = normalize(uniforms.cameraPos.xyz - vWorldPos);
+    let fresnel = 0.02 + 0.98 * pow(1. - max(dot(normal, to_camera), 0.), 5.);
+    let specular = light.color * pow(max(dot(normal, normalize(to_sun + to_camera)), 0.), 8. * light.shininess) * step(0., to_sun.y);
+
+    // light gets swallowed on its way through, deeper water shows less of the ground and more of its own color
+    let transmittance = exp(-water.absorption * depth);
+    let lit = light.ambient + light.color * max(to_sun.y, 0.);
+    let scattered = pow(water.color, vec3<f32>(2.2)) * lit * (1. - transmittance);
+    var color = (1. - fresnel) * scattered + fresnel * sky(reflect(-to_camera, normal)) + specular;
+    var alpha = 1. - (1. - fresnel) * dot(transmittance, vec3<f32>(1. / 3.));
+
+    // foam where it gets shallow, broken up by noise that moves with the waves
+    let shore = 1. - clamp(depth / water.foam_width, 0., 1.);
+    let pattern = 0.5 + 0.5 * perlinNoise2(vWorldPos.xz * 40. + vec2<f32>(water.time * 0.5, 0.)).x;
+    let foam = smoothstep(0.45, 0.75, shore * (0.6 + 0.8 * pattern));
+    color = mix(color, vec3<f32>(0.9) * lit, foam);
+    alpha = mix(alpha, 1., foam);
+
+    // the terrain behind is already fogged, only what the water adds still needs it
+    let fogged = apply_fog(color / max(alpha, 0.0001), vWorldPos) * alpha;
+    return vec4<f32>(fogged, alpha);
+}
+
+// the speed indicator in the top left corner, seven segment digits on a dark box, in pixels from there
+const HUD_ORIGIN = vec2<f32>(12., 12.);
+const HUD_PADDING = 8.;
+const HUD_DIGIT = vec2<f32>(10., 20.);
+const HUD_ADVANCE = 16.; // from one digit to the next
+const HUD_SLOTS = 7u; // four digits, the point and two decimals
+
+struct HudOutput {
+    @builtin(position) Position: vec4<f32>,
+    @location(0) vPixel: vec2<f32>
+};
+
+// a quad around the box, drawn over everything
+@vertex
+fn vs_hud(@builtin(vertex_index) index: u32) -> HudOutput {
+    var output: HudOutput;
+    let corner = vec2<f32>(f32((0x32u >> index) & 1u), f32((0x2Cu >> index) & 1u));
+    let size = vec2<f32>(f32(HUD_SLOTS) * HUD_ADVANCE - (HUD_ADVANCE - HUD_DIGIT.x), HUD_DIGIT.y) + 2. * HUD_PADDING;
+    output.vPixel = HUD_ORIGIN + corner * size;
+    output.Position = vec4<f32>(output.vPixel / uniforms.hud.screen * vec2<f32>(2., -2.) + vec2<f32>(-1., 1.), 0., 1.);
     return output;
 }
 
+// how much of the pixel at p is inside the box, one pixel of antialiasing
+fn box_coverage(p: vec2<f32>, center: vec2<f32>, half_size: vec2<f32>) -> f32 {
+    let d = abs(p - center) - half_size;
+    return clamp(0.5 - max(d.x, d.y), 0., 1.);
+}
+
+// p from the top left of the digit, bit 0 to 6 of mask are segments a to g (top, clockwise, then the middle)
+fn seven_segment(p: vec2<f32>, mask: u32) -> f32 {
+    let w = HUD_DIGIT.x;
+    let h = HUD_DIGIT.y;
+    let t = 1.25; // half the thickness
+    let horizontal = vec2<f32>(w / 2. - t, t);
+    let vertical = vec2<f32>(t, h / 4. - t);
+    var centers = array<vec2<f32>, 7>(
+        vec2<f32>(w / 2., 0.), vec2<f32>(w, h / 4.), vec2<f32>(w, 3. * h / 4.), vec2<f32>(w / 2., h),
+        vec2<f32>(0., 3. * h / 4.), vec2<f32>(0., h / 4.), vec2<f32>(w / 2., h / 2.)
+    );
+    var coverage = 0.;
+    for (var segment = 0u; segment < 7u; segment++) {
+        if ((mask & (1u << segment)) != 0u) {
+            let half_size = select(vertical, horizontal, segment == 0u || segment == 3u || segment == 6u);
+            coverage = max(coverage, box_coverage(p, centers[segment], half_size));
+        }
+    }
+    return coverage;
+}
+
 @fragment
-fn fs_main(@location(0) vColor: vec4<f32>) -> @location(0) vec4<f32> {
-    return vColor;
-}
\ No newline at end of file
+fn fs_hud(@location(0) vPixel: vec2<f32>) -> @location(0) vec4<f32> {
+    var segments = array<u32, 10>(0x3Fu, 0x06u, 0x5Bu, 0x4Fu, 0x66u, 0x6Du, 0x7Du, 0x07u, 0x7Fu, 0x6Fu);
+    // what each slot shows a digit of, in hundredths, the point has none
+    var places = array<u32, 7>(100000u, 10000u, 1000u, 100u, 0u, 10u, 1u);
+
+    let p = vPixel - HUD_ORIGIN - HUD_PADDING;
+    let slot = u32(clamp(floor(p.x / HUD_ADVANCE), 0., f32(HUD_SLOTS - 1u)));
+    let local = p - vec2<f32>(f32(slot) * HUD_ADVANCE, 0.);
+    let value = u32(round(clamp(uniforms.hud.speed, 0., 9999.99) * 100.));
+
+    var ink = 0.;
+    let place = places[slot];
+    if (place == 0u) {
+        ink = box_coverage(local, vec2<f32>(HUD_DIGIT.x / 2., HUD_DIGIT.y - 1.), vec2<f32>(1.5));
+    } else if (value >= place || place <= 100u) { // no leading zeros
+        ink = seven_segment(local, segments[(value / place) % 10u]);
+    }
+    // premultiplied, white on half transparent black
+    let opacity = uniforms.hud.opacity;
+    return vec4<f32>(vec3<f32>(ink * opacity), (0.5 + 0.5 * ink) * opacity);
+}
@@ -0,0 +1,91 @@
+use bytemuck::{Pod, Zeroable};
+use cgmath::{ortho, EuclideanSpace, InnerSpace, Matrix4, Point3, Transform, Vector3};
+use serde::Deserialize;
+
+// cascaded shadow maps, every cascade is a square around the camera seen from the sun, the closer ones are smaller so they are sharper
+pub const CASCADES: usize = 3;
+
+// how far each cascade reaches from the camera, as a part of the render distance
+const CASCADE_SPLITS: [f32; CASCADES] = [0.12, 0.35, 1.];
+
+// how far outside of a cascade chunks are still drawn into it, the terrain isn't high enough to throw longer shadows (except with the sun right on the horizon)
+pub const CASTER_MARGIN: f32 = 4.;
+
+// how much room there is towards the sun for things that cast shadows into a cascade
+const DEPTH_MARGIN: f32 = 20.;
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Shadows {
+    pub enabled: bool,
+    pub resolution: u32 // of each cascade, bigger is sharper and slower
+}
+
+impl Shadows {
+    // what every desktop gpu supports, the shadow map is clamped to the device limit on top of that
+    pub const MAX_RESOLUTION: u32 = 8192;
+}
+
+impl Default for Shadows {
+    fn default() -> Self {
+        Self { enabled: true, resolution: 2048 }
+    }
+}
+
+// the layout has to match the Shadows struct in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, Pod, Zeroable)]
+pub struct ShadowUniform {
+    radii: [f32; CASCADES],
+    texel: f32 // 1 / resolution, 0 turns the shadows off
+}
+
+impl ShadowUniform {
+    pub fn new(cascades: &[Cascade; CASCADES], resolution: u32) -> Self {
+        return Self { radii: cascades.each_ref().map(|cascade| cascade.radius), texel: 1. / resolution as f32 };
+    }
+}
+
+pub struct Cascade {
+    pub matrix: Matrix4<f32>, // world to light clip space, opengl style like the other projections
+    pub radius: f32 // in world units around the camera
+}
+
+// center is the middle of the terrain under the camera, not the camera itself, a camera high up would push the ground out of the small cascades
+// snapped to whole shadow map texels, so the shadow edges don't crawl when the camera moves
+pub fn cascades(center: [f32; 3], sun_direction: [f32; 3], render_distance: f32, resolution: u32) -> [Cascade; CASCADES] {
+    let direction = Vector3::from(sun_direction).normalize();
+    let up = if direction.y.abs() > 0.99 { Vector3::unit_z() } else { Vector3::unit_y() };
+    let light_view = Matrix4::look_at_rh(Point3::from_vec(direction), Point3::origin(), up);
+    let center = light_view.transform_point(Point3::from(center));
+
+    return CASCADE_SPLITS.map(|split| {
+        let radius = split * render_distance;
+        let texel = 2. * radius / resolution as f32;
+        let (x, y) = ((center.x / texel).round() * texel, (center.y / texel).round() * texel);
+        // view space looks down -z
+        let depth = -center.z;
+        let projection = ortho(x - radius, x + radius, y - radius, y + radius, depth - radius - DEPTH_MARGIN, depth + radius + DEPTH_MARGIN);
+        return Cascade { matrix: projection * light_view, radius };
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::Vector4;
+
+    #[test]
+    fn cascades_cover_their_radius() {
+        let center = [12.3, 0.5, -40.];
+        for sun in [[0.4, 0.6, 0.3], [0., 1., 0.], [-1., 0.05, 0.]] {
+            for cascade in cascades(center, sun, 20., 2048) {
+                for offset in [[0., 0.], [0.99, 0.], [0., -0.99], [0.7, 0.7]] {
+                    let point = Vector4::new(center[0] + offset[0] * cascade.radius, center[1], center[2] + offset[1] * cascade.radius, 1.);
+                    let clip = cascade.matrix * point;
+                    assert!(clip.x.abs() <= 1. && clip.y.abs() <= 1. && clip.z.abs() <= 1., "{:?} outside with sun {:?}", offset, sun);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+// side length of a level 0 chunk in world units, a chunk on level n is 2^n times as big
+pub const CHUNK_SIZE: f32 = 1.;
+
+// which chunk, counted in chunks of its level from the origin, chunk (0, 0) on level 0 covers 0..CHUNK_SIZE on x and z
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkCoord {
+    pub level: u32,
+    pub x: i32,
+    pub z: i32
+}
+
+impl ChunkCoord {
+    pub fn new(level: u32, x: i32, z: i32) -> Self {
+        return Self { level, x, z };
+    }
+
+    pub fn containing(level: u32, x: f32, z: f32) -> Self {
+        let size = Self::new(level, 0, 0).size();
+        return Self::new(level, (x / size).floor() as i32, (z / size).floor() as i32);
+    }
+
+    pub fn size(&self) -> f32 {
+        return CHUNK_SIZE * (1u32 << self.level) as f32;
+    }
+
+    // the corner with the smallest coordinates
+    pub fn origin(&self) -> [f32; 2] {
+        return [self.x as f32 * self.size(), self.z as f32 * self.size()];
+    }
+
+    // the four chunks one level down covering this one
+    pub fn children(&self) -> [ChunkCoord; 4] {
+        let (level, x, z) = (self.level - 1, self.x * 2, self.z * 2);
+        return [Self::new(level, x, z), Self::new(level, x + 1, z), Self::new(level, x, z + 1), Self::new(level, x + 1, z + 1)];
+    }
+
+    // from pos to the closest point of the chunk, 0 if pos is inside
+    pub fn distance_to(&self, pos: [f32; 2]) -> f32 {
+        let (origin, size) = (self.origin(), self.size());
+        let dx = (origin[0] - pos[0]).max(pos[0] - origin[0] - size).max(0.);
+        let dz = (origin[1] - pos[1]).max(pos[1] - origin[1] - size).max(0.);
+        return (dx * dx + dz * dz).sqrt();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn children_cover_the_parent() {
+        let parent = ChunkCoord::new(2, -1, 3);
+        for child in parent.children() {
+            assert_eq!(child.size() * 2., parent.size());
+            assert_eq!(ChunkCoord::containing(2, child.origin()[0], child.origin()[1]), parent);
+        }
+    }
+
+    #[test]
+    fn negative_coordinates_round_down() {
+        assert_eq!(ChunkCoord::containing(0, -0.1 * CHUNK_SIZE, 0.), ChunkCoord::new(0, -1, 0));
+        assert_eq!(ChunkCoord::new(0, -1, 0).distance_to([-0.5 * CHUNK_SIZE, 0.5 * CHUNK_SIZE]), 0.);
+    }
+}
@@ -0,0 +1,31 @@
+use crate::settings::Settings;
+use crate::terrain::chunk::CHUNK_SIZE;
+use crate::terrain::noise::{self, NoiseParams, NoiseSeed};
+
+// the terrain as something to stand on, evaluated on the cpu with the same noise vs_main uses
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Ground {
+    params: NoiseParams,
+    seed: NoiseSeed,
+    filter_width: f32 // the quad size of the closest chunks, so it's as detailed as the mesh under the player and no more
+}
+
+impl Ground {
+    pub fn new(settings: &Settings) -> Self {
+        return Self {
+            params: settings.noise,
+            seed: NoiseSeed::from_seed(settings.seed),
+            filter_width: CHUNK_SIZE / settings.quality.quads_per_chunk() as f32
+        };
+    }
+
+    // world space y of the surface at (x, z)
+    pub fn height(&self, x: f32, z: f32) -> f32 {
+        return self.sample(x, z)[0];
+    }
+
+    // height and how much it changes along x and z, the gradient points uphill
+    pub fn sample(&self, x: f32, z: f32) -> [f32; 3] {
+        return noise::height(x, z, self.filter_width, &self.params, &self.seed);
+    }
+}
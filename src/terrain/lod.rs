@@ -0,0 +1,88 @@
+// continuous distance-dependent level of detail (cdlod, Strugar 2009)
+// every chunk has the same number of quads no matter its level, so a level n chunk is 2^n times coarser than a level 0 one
+// a chunk is split into its four children while the camera is within the range of the level below,
+// and its vertices slowly morph onto the grid of the level above when getting close to the end of its range,
+// so there are no cracks between levels and nothing pops
+use crate::terrain::chunk::{ChunkCoord, CHUNK_SIZE};
+
+// how far level 0 reaches, every level reaches twice as far as the one below
+// has to be at least 4 * sqrt(2) chunks for MORPH_START to work out (see morph_range)
+pub const LOD0_RANGE: f32 = 6. * CHUNK_SIZE;
+
+// where in its range a level starts morphing into the next one
+const MORPH_START: f32 = 0.75;
+
+pub fn lod_range(level: u32) -> f32 {
+    return LOD0_RANGE * (1u32 << level) as f32;
+}
+
+// the distances between which a chunk of this level goes from its own grid to the one of the level above
+// the children of a split chunk reach up to lod_range(level - 1) + the diagonal of their parent, and where they meet
+// an unsplit neighbour of this level that one can't have started morphing yet, hence the limit on LOD0_RANGE
+pub fn morph_range(level: u32) -> (f32, f32) {
+    let range = lod_range(level);
+    return (range * MORPH_START, range);
+}
+
+// all chunks needed to cover radius around center, closest first
+pub fn select(center: [f32; 2], radius: f32) -> Vec<ChunkCoord> {
+    let mut top = 0;
+    while lod_range(top) < radius {
+        top += 1;
+    }
+
+    let min = ChunkCoord::containing(top, center[0] - radius, center[1] - radius);
+    let max = ChunkCoord::containing(top, center[0] + radius, center[1] + radius);
+    let mut selected = Vec::new();
+    for z in min.z..=max.z {
+        for x in min.x..=max.x {
+            select_chunk(ChunkCoord::new(top, x, z), center, radius, &mut selected);
+        }
+    }
+    selected.sort_by(|a, b| a.distance_to(center).total_cmp(&b.distance_to(center)));
+    return selected;
+}
+
+fn select_chunk(coord: ChunkCoord, center: [f32; 2], radius: f32, selected: &mut Vec<ChunkCoord>) {
+    let distance = coord.distance_to(center);
+    if distance > radius {
+        return;
+    }
+    if coord.level > 0 && distance <= lod_range(coord.level - 1) {
+        for child in coord.children() {
+            select_chunk(child, center, radius, selected);
+        }
+    } else {
+        selected.push(coord);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn morphing_is_done_where_split_chunks_end() {
+        for level in 1..8 {
+            let parent_diagonal = std::f32::consts::SQRT_2 * ChunkCoord::new(level, 0, 0).size();
+            assert!(lod_range(level - 1) + parent_diagonal <= morph_range(level).0);
+        }
+    }
+
+    #[test]
+    fn selection_has_no_overlaps_and_gets_coarser() {
+        let center = [3.3, -7.9];
+        let selected = select(center, 50.);
+        for (i, a) in selected.iter().enumerate() {
+            for b in &selected[i + 1..] {
+                let (a_origin, b_origin) = (a.origin(), b.origin());
+                let overlap_x = a_origin[0] < b_origin[0] + b.size() && b_origin[0] < a_origin[0] + a.size();
+                let overlap_z = a_origin[1] < b_origin[1] + b.size() && b_origin[1] < a_origin[1] + a.size();
+                assert!(!(overlap_x && overlap_z), "{:?} overlaps {:?}", a, b);
+            }
+        }
+        assert_eq!(selected[0].level, 0);
+        assert!(selected.iter().any(|coord| coord.level >= 3));
+        assert!(selected.iter().all(|coord| coord.level == 0 || coord.distance_to(center) > lod_range(coord.level - 1)));
+    }
+}
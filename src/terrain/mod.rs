@@ -0,0 +1,4 @@
+pub mod noise;
+pub mod chunk;
+pub mod lod;
+pub mod ground;
@@ -0,0 +1,311 @@
+// cpu port of the terrain noise in shader.wgsl
+// if you change something in the shader, change it here too (and the other way around), the tests below will tell you if they drifted apart
+
+use bytemuck::{Pod, Zeroable};
+
+// knobs for fbm and domain_warp, uploaded to the shader as part of the uniform buffer
+// the layout has to match the NoiseParams struct in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NoiseParams {
+    pub amplitude: f32,
+    pub frequency: f32,
+    pub octave_count: u32,
+    pub persistence: f32,
+    pub lacunarity: f32,
+    pub warps: u32,
+    pub falloff: f32,
+    pub warp_scale: f32,
+}
+
+impl NoiseParams {
+    // both loops run per vertex and per pixel on the gpu, past this there's no visible detail left, just stalls
+    pub const MAX_OCTAVES: u32 = 16;
+    pub const MAX_WARPS: u32 = 8;
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        Self {
+            amplitude: 1.25,
+            frequency: 0.7,
+            octave_count: 7,
+            persistence: 0.45,
+            lacunarity: 2.5,
+            warps: 2,
+            falloff: 0.9,
+            warp_scale: 0.3,
+        }
+    }
+}
+
+// what a world seed turns into on the gpu, a shift of the noise position and a rotation of the gradient lookup in perlin_noise2
+// the layout has to match the NoiseSeed struct in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Pod, Zeroable)]
+pub struct NoiseSeed {
+    pub offset: [f32; 2],
+    pub salt: f32,
+    _padding: f32,
+}
+
+// splitmix64, good enough to spread similar seeds far apart
+fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    return z ^ (z >> 31);
+}
+
+// uniform float in [0, 1)
+fn next_unit(state: &mut u64) -> f32 {
+    return (next_random(state) >> 40) as f32 / (1u64 << 24) as f32;
+}
+
+impl NoiseSeed {
+    // the offset is kept small so f32 precision doesn't start eating the high octaves
+    const MAX_OFFSET: f32 = 256.;
+
+    // seed 0 is the original world from before seeds existed
+    pub fn from_seed(seed: u64) -> Self {
+        if seed == 0 {
+            return Self::default();
+        }
+        let mut state = seed;
+        let offset = [
+            (next_unit(&mut state) * 2. - 1.) * Self::MAX_OFFSET,
+            (next_unit(&mut state) * 2. - 1.) * Self::MAX_OFFSET
+        ];
+        let salt = next_unit(&mut state);
+        return Self { offset, salt, _padding: 0. };
+    }
+}
+
+// wgsl's fract is x - floor(x), rust's f32::fract truncates which is wrong for negative numbers
+fn fract(x: f32) -> f32 {
+    return x - x.floor();
+}
+
+// wgsl's mix
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    return a * (1. - t) + b * t;
+}
+
+fn permute4(x: [f32; 4]) -> [f32; 4] {
+    return x.map(|x| ((x * 34. + 1.) * x) % 289.);
+}
+
+fn fade(t: f32) -> f32 {
+    return t * t * t * (t * (t * 6. - 15.) + 10.);
+}
+
+fn fade_derivative(t: f32) -> f32 {
+    return 30. * t * t * (t * (t - 2.) + 1.);
+}
+
+// value, d/dx and d/dz (well, d/dy of p) of the noise
+#[allow(clippy::excessive_precision)] // same constants as the shader
+pub fn perlin_noise2(p: [f32; 2], salt: f32) -> [f32; 3] { // credit to Stefan Gustavson for this perlin implementation
+    let floor = [p[0].floor(), p[1].floor()];
+    let pf = [fract(p[0]), fract(p[1])];
+
+    // corners in the order 00, 10, 01, 11 (same as Pi.xzxz / Pi.yyww in the shader)
+    let ix = [floor[0], floor[0] + 1., floor[0], floor[0] + 1.].map(|x| x % 289.);
+    let iy = [floor[1], floor[1], floor[1] + 1., floor[1] + 1.].map(|y| y % 289.);
+    let fx = [pf[0], pf[0] - 1., pf[0], pf[0] - 1.];
+    let fy = [pf[1], pf[1], pf[1] - 1., pf[1] - 1.];
+
+    let permuted = permute4(ix);
+    let i = permute4([0, 1, 2, 3].map(|c| permuted[c] + iy[c]));
+
+    let mut g = [[0.; 2]; 4];
+    let mut n = [0.; 4];
+    for c in 0..4 {
+        let mut gx = 2. * fract(i[c] * 0.0243902439 + salt) - 1.;
+        let gy = gx.abs() - 0.5;
+        gx -= (gx + 0.5).floor();
+        let norm = 1.79284291400159 - 0.85373472095314 * (gx * gx + gy * gy);
+        g[c] = [gx * norm, gy * norm];
+        n[c] = g[c][0] * fx[c] + g[c][1] * fy[c];
+    }
+
+    let fade_x = fade(pf[0]);
+    let fade_y = fade(pf[1]);
+    let n_x = [mix(n[0], n[1], fade_x), mix(n[2], n[3], fade_x)];
+    let n_xy = mix(n_x[0], n_x[1], fade_y);
+
+    // the corner dot products are linear with their gradient as slope, plus the change of the fade weights
+    let g_x = [0, 1].map(|d| [mix(g[0][d], g[1][d], fade_x), mix(g[2][d], g[3][d], fade_x)]);
+    let dx = mix(g_x[0][0], g_x[0][1], fade_y) + fade_derivative(pf[0]) * mix(n[1] - n[0], n[3] - n[2], fade_y);
+    let dy = mix(g_x[1][0], g_x[1][1], fade_y) + fade_derivative(pf[1]) * (n_x[1] - n_x[0]);
+    return [2.3 * n_xy, 2.3 * dx, 2.3 * dy];
+}
+
+// how much of an octave goes into the derivatives, all of it up to half of max_frequency and none from max_frequency on
+fn derivative_weight(frequency: f32, max_frequency: f32) -> f32 {
+    return (2. * (1. - frequency / max_frequency)).clamp(0., 1.);
+}
+
+// value and derivatives like perlin_noise2
+// octaves above max_frequency are left out of the derivatives (not the value), the mesh is too coarse to show them anyway
+pub fn fbm(pos: [f32; 2], max_frequency: f32, params: &NoiseParams, seed: &NoiseSeed) -> [f32; 3] {
+    let mut amplitude = params.amplitude;
+    let mut frequency = params.frequency;
+
+    let mut value = [0.; 3];
+    for _ in 0..params.octave_count {
+        let octave = perlin_noise2([pos[0] * frequency, pos[1] * frequency], seed.salt);
+        let slope = amplitude * frequency * derivative_weight(frequency, max_frequency);
+        value[0] += amplitude * octave[0];
+        value[1] += slope * octave[1];
+        value[2] += slope * octave[2];
+        amplitude *= params.persistence;
+        frequency *= params.lacunarity;
+    }
+    return value;
+}
+
+// a warped position and how it moves when the input moves along x and along z
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Warp {
+    pub pos: [f32; 2],
+    pub ddx: [f32; 2],
+    pub ddz: [f32; 2]
+}
+
+pub fn domain_warp(pos: [f32; 2], max_frequency: f32, params: &NoiseParams, seed: &NoiseSeed) -> Warp {
+    let mut scale = params.warp_scale;
+
+    let mut warp = Warp { pos, ddx: [1., 0.], ddz: [0., 1.] };
+    for _ in 0..params.warps {
+        let a = fbm(pos, max_frequency, params, seed);
+        let b = fbm([-pos[0], -pos[1]], max_frequency, params, seed);
+        warp.pos[0] += scale * a[0];
+        warp.pos[1] += scale * b[0];
+        // b is sampled at -pos, so its derivatives flip
+        warp.ddx[0] += scale * a[1];
+        warp.ddz[0] += scale * a[2];
+        warp.ddx[1] -= scale * b[1];
+        warp.ddz[1] -= scale * b[2];
+        scale *= params.falloff;
+    }
+    return warp;
+}
+
+// value, d/dx and d/dz at world position pos
+// the derivatives only have detail down to about filter_width world units, 0 keeps all of it
+pub fn noise(pos: [f32; 2], filter_width: f32, params: &NoiseParams, seed: &NoiseSeed) -> [f32; 3] {
+    // a wave has to be at least two filter widths long, and noise space is half as big as the world
+    let max_frequency = 1. / filter_width;
+    let warp = domain_warp([(pos[0] + seed.offset[0]) * 0.5, (pos[1] + seed.offset[1]) * 0.5], max_frequency, params, seed);
+    let value = fbm(warp.pos, max_frequency, params, seed);
+    // chain rule through the warp, the 0.5 is the scale of pos above
+    let dx = 0.5 * (value[1] * warp.ddx[0] + value[2] * warp.ddx[1]);
+    let dz = 0.5 * (value[1] * warp.ddz[0] + value[2] * warp.ddz[1]);
+    return [value[0], dx, dz];
+}
+
+// the height vs_main adds to a vertex at noise position (x, z), and its slope along x and z (see noise for filter_width)
+pub fn height(x: f32, z: f32, filter_width: f32, params: &NoiseParams, seed: &NoiseSeed) -> [f32; 3] {
+    let noise = noise([x, z], filter_width, params, seed);
+    return [(noise[0] + 1.) / 2., noise[1] / 2., noise[2] / 2.];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // golden values were computed with a double precision reference of the shader code
+    const POSITIONS: [[f32; 2]; 6] = [[0.0, 0.0], [0.5, 0.25], [1.3, -2.7], [-3.75, 4.1], [12.34, 56.78], [-100.5, 250.25]];
+
+    fn assert_close(actual: f32, expected: f64) {
+        assert!((actual as f64 - expected).abs() < 1e-4, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn perlin_matches_golden_values() {
+        let expected = [0.0, -0.28268178063509664, 0.5205255983271824, 0.5051148122497303, -0.20435633085606425, 0.3119848624938185];
+        for (pos, expected) in POSITIONS.iter().zip(expected) {
+            assert_close(perlin_noise2(*pos, 0.)[0], expected);
+        }
+    }
+
+    #[test]
+    fn fbm_matches_golden_values() {
+        let expected = [0.0, -0.3326117012392758, -0.3295800152538302, -0.808728946205904, -0.4220062866336092, -0.06257509724415503];
+        for (pos, expected) in POSITIONS.iter().zip(expected) {
+            assert_close(fbm(*pos, f32::INFINITY, &NoiseParams::default(), &NoiseSeed::default())[0], expected);
+        }
+    }
+
+    #[test]
+    fn noise_matches_golden_values() {
+        let expected = [0.0, 0.11611244592842833, 0.2234614463648534, -0.10700188252203979, 0.6976579126800969, 0.4158601969115205];
+        for (pos, expected) in POSITIONS.iter().zip(expected) {
+            assert_close(noise(*pos, 0., &NoiseParams::default(), &NoiseSeed::default())[0], expected);
+        }
+    }
+
+    #[test]
+    fn height_is_shifted_noise() {
+        assert_close(height(0., 0., 0., &NoiseParams::default(), &NoiseSeed::default())[0], 0.5);
+        assert_close(height(12.34, 56.78, 0., &NoiseParams::default(), &NoiseSeed::default())[0], (0.6976579126800969 + 1.) / 2.);
+    }
+
+    // central differences, the analytic derivatives have to match them
+    fn assert_derivatives(f: impl Fn([f32; 2]) -> [f32; 3]) {
+        let h = 1e-3;
+        for pos in POSITIONS {
+            let value = f(pos);
+            let dx = (f([pos[0] + h, pos[1]])[0] - f([pos[0] - h, pos[1]])[0]) / (2. * h);
+            let dz = (f([pos[0], pos[1] + h])[0] - f([pos[0], pos[1] - h])[0]) / (2. * h);
+            let tolerance = 1e-2 * (1. + dx.abs().max(dz.abs()));
+            assert!((value[1] - dx).abs() < tolerance && (value[2] - dz).abs() < tolerance, "at {:?} expected {} {}, got {} {}", pos, dx, dz, value[1], value[2]);
+        }
+    }
+
+    #[test]
+    fn derivatives_match_finite_differences() {
+        // few octaves, the high ones change too fast for finite differences
+        let params = NoiseParams { octave_count: 3, ..Default::default() };
+        let seed = NoiseSeed::from_seed(7);
+        assert_derivatives(|pos| perlin_noise2(pos, seed.salt));
+        assert_derivatives(|pos| fbm(pos, f32::INFINITY, &params, &seed));
+        assert_derivatives(|pos| noise(pos, 0., &params, &seed));
+    }
+
+    #[test]
+    fn filtering_only_touches_derivatives() {
+        let (params, seed) = (NoiseParams::default(), NoiseSeed::default());
+        let sharp = noise([1.3, -2.7], 0., &params, &seed);
+        let smooth = noise([1.3, -2.7], 0.1, &params, &seed);
+        assert_eq!(sharp[0], smooth[0]);
+        assert_ne!(sharp[1], smooth[1]);
+        assert_eq!(noise([1.3, -2.7], 1000., &NoiseParams { octave_count: 1, ..params }, &seed)[1..], [0., 0.]);
+    }
+
+    #[test]
+    fn no_octaves_is_flat() {
+        let params = NoiseParams { octave_count: 0, ..Default::default() };
+        for pos in POSITIONS {
+            assert_eq!(noise(pos, 0., &params, &NoiseSeed::default()), [0.; 3]);
+        }
+    }
+
+    #[test]
+    fn seeds_are_reproducible() {
+        assert_eq!(NoiseSeed::from_seed(0), NoiseSeed::default());
+        assert_eq!(NoiseSeed::from_seed(1234), NoiseSeed::from_seed(1234));
+
+        let params = NoiseParams::default();
+        let a = NoiseSeed::from_seed(1);
+        let b = NoiseSeed::from_seed(2);
+        assert_ne!(a, b);
+        assert!(a.offset.iter().all(|o| o.abs() <= NoiseSeed::MAX_OFFSET));
+        assert!((0. ..1.).contains(&a.salt));
+        assert_eq!(height(3., 4., 0., &params, &a), height(3., 4., 0., &params, &a));
+        assert_ne!(height(3., 4., 0., &params, &a), height(3., 4., 0., &params, &b));
+    }
+}
@@ -0,0 +1,97 @@
+use std::f32::consts::TAU;
+use crate::light::Light;
+use crate::settings::TimeSettings;
+
+// the in-game clock, it moves the sun and with it the light and sky colors
+#[derive(Default)]
+pub struct TimeOfDay {
+    pub hour: f32, // 0 to 24, sunrise at 6 and sunset at 18
+    pub paused: bool,
+    day_length: f32 // real seconds for 24 hours
+}
+
+impl TimeOfDay {
+    // hours per second while a scrub key is held
+    pub const SCRUB_SPEED: f32 = 3.;
+
+    // how far the noon sun is from straight up, in radians
+    const SUN_TILT: f32 = 0.6;
+
+    pub fn new(settings: &TimeSettings) -> Self {
+        return Self { hour: settings.start_hour.rem_euclid(24.), paused: settings.paused, day_length: settings.day_length };
+    }
+
+    // only the speed, jumping to the start hour on every save would be annoying
+    pub fn apply_settings(&mut self, settings: &TimeSettings) {
+        self.day_length = settings.day_length;
+    }
+
+    pub fn advance(&mut self, dt: f32) {
+        if !self.paused && self.day_length > 0. {
+            self.scrub(24. * dt / self.day_length);
+        }
+    }
+
+    pub fn scrub(&mut self, hours: f32) {
+        self.hour = (self.hour + hours).rem_euclid(24.);
+    }
+
+    // towards the sun, rises in +x and sets in -x
+    pub fn sun_direction(&self) -> [f32; 3] {
+        let angle = (self.hour - 6.) / 24. * TAU;
+        let (sin, cos) = angle.sin_cos();
+        return [cos, sin * Self::SUN_TILT.cos(), sin * Self::SUN_TILT.sin()];
+    }
+
+    // base is the light at noon, from the settings
+    pub fn light(&self, base: &Light) -> Light {
+        let direction = self.sun_direction();
+        let elevation = direction[1];
+
+        // reddish when low, gone below the horizon
+        let daylight = smoothstep(-0.05, 0.1, elevation);
+        let warmth = 1. - smoothstep(0., 0.4, elevation);
+        let tint = [1., 0.55, 0.3];
+        let color = [0, 1, 2].map(|c| base.color[c] * (1. + (tint[c] - 1.) * warmth) * daylight);
+
+        // a bit of blue moonlight at night
+        let day = smoothstep(-0.2, 0.2, elevation);
+        let night = [0.03, 0.04, 0.08];
+        let ambient = [0, 1, 2].map(|c| night[c] + (base.ambient[c] - night[c]) * day);
+        return Light { direction, color, ambient, ..*base };
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0., 1.);
+    return t * t * (3. - 2. * t);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clock(hour: f32) -> TimeOfDay {
+        return TimeOfDay::new(&TimeSettings { start_hour: hour, ..Default::default() });
+    }
+
+    #[test]
+    fn sun_is_up_at_noon_and_down_at_midnight() {
+        assert!(clock(12.).sun_direction()[1] > 0.7);
+        assert!(clock(0.).sun_direction()[1] < -0.7);
+        assert!(clock(6.).sun_direction()[1].abs() < 1e-5);
+        assert_eq!(clock(0.).light(&Light::default()).color, [0.; 3]);
+    }
+
+    #[test]
+    fn wraps_around_and_pauses() {
+        let mut time = TimeOfDay { hour: 23., paused: false, day_length: 24. };
+        time.advance(2.);
+        assert!((time.hour - 1.).abs() < 1e-5);
+        time.scrub(-3.);
+        assert!((time.hour - 22.).abs() < 1e-5);
+        time.paused = true;
+        time.advance(5.);
+        assert!((time.hour - 22.).abs() < 1e-5);
+    }
+}
@@ -1,6 +1,5 @@
 
-use std::f32::consts::PI;
-use cgmath::{ortho, perspective, Matrix4, Point3, Rad, Vector3};
+use cgmath::{ortho, perspective, Deg, Matrix4, Point3, Rad, Vector3};
 
 pub fn create_view(cam_pos: Point3<f32>, cam_look_dir: Point3<f32>, up_direction: Vector3<f32>) -> Matrix4<f32> {
 
@@ -10,14 +9,12 @@ pub fn create_view(cam_pos: Point3<f32>, cam_look_dir: Point3<f32>, up_direction
 
 }
 
-pub fn create_projection(aspect: f32, is_perspective: bool) -> Matrix4<f32> {
-    let project_mat: Matrix4<f32>;
-
-    if is_perspective {
-        project_mat = perspective(Rad(2.0 * PI / 5.0), aspect, 0.1, 100.0);
+pub fn create_projection(aspect: f32, fov: f32, is_perspective: bool) -> Matrix4<f32> {
+    let project_mat: Matrix4<f32> = if is_perspective {
+        perspective(Deg(fov), aspect, 0.1, 100.0)
     } else {
-        project_mat = ortho(-4.0, 4.0, -3.0, 3.0, -1.0, 6.0);
-    }
+        ortho(-4.0, 4.0, -3.0, 3.0, -1.0, 6.0)
+    };
 
     return project_mat;
 }
@@ -21,6 +21,10 @@ pub fn sqr_magnitude(vector: Vector3) -> f32 {
     return vector.x * vector.x + vector.y * vector.y + vector.z * vector.z;
 }
 
+pub fn dot(a: Vector3, b: Vector3) -> f32 {
+    return a.x * b.x + a.y * b.y + a.z * b.z;
+}
+
 pub fn magnitude(vector: Vector3) -> f32 {
     return (vector.x * vector.x + vector.y * vector.y + vector.z * vector.z).sqrt();
 }
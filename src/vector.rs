@@ -12,7 +12,7 @@ pub struct Vector3 {
 
 impl Vector3 {
     pub fn new(x: f32, y: f32, z: f32) -> Self {
-        Self { x: x, y: y, z: z }
+        Self { x, y, z }
     }
 
     pub fn rad(&self) -> Vector3 {
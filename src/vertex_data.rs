@@ -1,93 +1,138 @@
-use std::process::exit;
-
-
-
+// a grid of quads as an indexed triangle list
 pub struct VertexData {
-    pub length: u32,
-    pub positions: Vec<[f32; 3]>
+    pub positions: Vec<[f32; 3]>,
+    pub indices: Vec<u32>
 }
 
-// create a plane (triangle_strip) of N*N quads where each quad is x*x big
-fn plane_vertices(n: f32, x: f32) -> Vec<[f32; 3]> {
-    let mut vertices = Vec::<[f32; 3]>::new();
-    for nz in 0..(n as u32) {
-        for nx in 0..=(n as u32) {
-            vertices.push([nx as f32, 0., nz as f32]);
-            vertices.push([nx as f32, 0., nz as f32 + 1.]);
-        }
-        if nz < (n as u32) - 1 {
-            vertices.push([n as f32, 0., nz as f32 + 1.]);
-            vertices.push([0., 0., nz as f32 + 1.]);
+// (n + 1) * (n + 1) row major vertices from 0 to 1 on x and z, matching grid_indices(n, n)
+fn grid_vertices(n: u32) -> Vec<[f32; 3]> {
+    let mut vertices = Vec::<[f32; 3]>::with_capacity(((n + 1) * (n + 1)) as usize);
+    for z in 0..=n {
+        for x in 0..=n {
+            vertices.push([x as f32 / n as f32, 0., z as f32 / n as f32]);
         }
     }
-    return vertices.iter().map(|&vert| { 
-        return [vert[0] * x, 0., vert[2] * x]; 
-    }).collect();
+    return vertices;
 }
 
-pub fn render_dist_mul() -> f32 {
-    match VertexData::RENDER_DISTANCE {
-        0 => {
-            return 0.5;
-        }
-        1 => {
-            return 1.;
-        }
-        2 => {
-            return 2.;
-        }
-        3 => {
-            return 4.;
-        },
-        4 => {
-            return 8.;
-        }
-        _ => {
-            exit(-1); // no
+// indices for a triangle list over a grid of (columns + 1) * (rows + 1) row major vertices, counter clockwise seen from +y
+// the caller has to make sure the vertex count fits in a u32, see TerrainMesh::generate
+pub fn grid_indices(columns: u32, rows: u32) -> Vec<u32> {
+    let mut indices = Vec::<u32>::with_capacity(columns as usize * rows as usize * 6);
+    for z in 0..rows {
+        for x in 0..columns {
+            let i00 = z * (columns + 1) + x;
+            let i10 = i00 + 1;
+            let i01 = i00 + columns + 1;
+            let i11 = i01 + 1;
+            indices.extend([i00, i01, i10, i10, i01, i11]);
         }
     }
+    return indices;
 }
 
-impl VertexData {
+// the numbers still work on the command line and in terrain.toml, so --quality 2 and --quality mid are the same
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Quality {
+    #[value(alias = "0")]
+    #[serde(alias = "0")]
+    Ultralow, // why
+    #[value(alias = "1")]
+    #[serde(alias = "1")]
+    Low,
+    #[default]
+    #[value(alias = "2")]
+    #[serde(alias = "2")]
+    Mid,
+    #[value(alias = "3")]
+    #[serde(alias = "3")]
+    High,
+    #[value(alias = "4")]
+    #[serde(alias = "4")]
+    Ultra // god pc only
+}
 
-    pub const QUALITY: u32 = 2; 
-    // 0: ultralow (why), 1: low, 2: mid, 3: high, 4: ultra (god pc only)
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RenderDistance {
+    #[value(alias = "0")]
+    #[serde(alias = "0")]
+    Half,
+    #[value(alias = "1")]
+    #[serde(alias = "1")]
+    Default,
+    #[default]
+    #[value(alias = "2")]
+    #[serde(alias = "2")]
+    Double,
+    #[value(alias = "3")]
+    #[serde(alias = "3")]
+    Quadruple,
+    #[value(alias = "4", name = "8x")]
+    #[serde(rename = "8x", alias = "4")]
+    Octuple
+}
 
-    pub const RENDER_DISTANCE: u32 = 2;
-    // 0: half, 1: default, 2: double, 3: quadruple, 4: 8x
-    
-    pub fn new() -> Self {
-        let positions = Self::vertex_positions();
-        let length = positions.len() as u32;
-        println!("Created {} vertices", length);
-        return Self {
-            length,
-            positions
-        };
+impl Quality {
+    // per side, the same on every lod level so farther chunks are coarser, has to be even for the morphing
+    pub fn quads_per_chunk(&self) -> u32 {
+        match self {
+            Quality::Ultralow => {
+                return 16;
+            }
+            Quality::Low => {
+                return 32;
+            }
+            Quality::Mid => {
+                return 64;
+            }
+            Quality::High => {
+                return 128;
+            }
+            Quality::Ultra => {
+                return 256;
+            }
+        }
+    }
+}
+
+impl RenderDistance {
+    // how far the terrain reaches around the player, in world units
+    pub fn radius(&self) -> f32 {
+        return 10. * self.multiplier();
     }
 
-    fn vertex_positions() -> Vec<[f32; 3]> {
-        let mul = render_dist_mul();
-        match Self::QUALITY {
-            0 => {
-                return plane_vertices(125. * mul, 0.04);
+    pub fn multiplier(&self) -> f32 {
+        match self {
+            RenderDistance::Half => {
+                return 0.5;
             }
-            1 => {
-                return plane_vertices(250. * mul, 0.02);
+            RenderDistance::Default => {
+                return 1.;
             }
-            2 => {
-                return plane_vertices(500. * mul, 0.01);
+            RenderDistance::Double => {
+                return 2.;
             }
-            3 => {
-                return plane_vertices(1000. * mul, 0.005);
-            },
-            4 => {
-                return plane_vertices(2000. * mul, 0.0025);
+            RenderDistance::Quadruple => {
+                return 4.;
             }
-            _ => {
-                exit(-1); // no
+            RenderDistance::Octuple => {
+                return 8.;
             }
         }
     }
+}
+
+impl VertexData {
+
+    // the grid every chunk is drawn with, the shader scales it to the chunk and adds the height
+    pub fn chunk_grid(quality: Quality) -> Self {
+        let quads = quality.quads_per_chunk();
+        return Self {
+            positions: grid_vertices(quads),
+            indices: grid_indices(quads, quads)
+        };
+    }
 
 }
@@ -0,0 +1,58 @@
+use bytemuck::{Pod, Zeroable};
+use serde::Deserialize;
+
+// the sea, drawn as a see-through plane over the terrain, heights are in terrain units (0 to about 1)
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Water {
+    pub level: f32,
+    pub color: [f32; 3], // srgb, what deep water looks like
+    pub absorption: [f32; 3], // how much red, green and blue get swallowed per unit of depth, higher is murkier
+    pub foam_width: f32, // how shallow the water has to be for foam
+    pub wave_scale: f32, // frequency of the ripples
+    pub wave_strength: f32,
+    pub wave_speed: f32
+}
+
+impl Default for Water {
+    fn default() -> Self {
+        Self {
+            level: 0.3,
+            color: [0.03, 0.2, 0.25],
+            absorption: [40., 16., 10.],
+            foam_width: 0.015,
+            wave_scale: 12.,
+            wave_strength: 0.25,
+            wave_speed: 0.15
+        }
+    }
+}
+
+// the layout has to match the Water struct in shader.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+pub struct WaterUniform {
+    level: f32,
+    time: f32, // seconds, moves the waves
+    foam_width: f32,
+    wave_scale: f32,
+    color: [f32; 3],
+    wave_strength: f32,
+    absorption: [f32; 3],
+    wave_speed: f32
+}
+
+impl Water {
+    pub fn uniform(&self, time: f32) -> WaterUniform {
+        return WaterUniform {
+            level: self.level,
+            time,
+            foam_width: self.foam_width,
+            wave_scale: self.wave_scale,
+            color: self.color,
+            wave_strength: self.wave_strength,
+            absorption: self.absorption,
+            wave_speed: self.wave_speed
+        };
+    }
+}
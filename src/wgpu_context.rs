@@ -1,34 +1,166 @@
 
 use crate::vector::Vector3;
-use crate::vertex_data::{self, VertexData};
+use crate::error::TerrainError;
+use crate::settings::{ScreenshotSettings, Settings};
+use crate::terrain::chunk::ChunkCoord;
+use crate::terrain::lod;
+use crate::vertex_data::{Quality, VertexData};
 use crate::{transforms, utils};
 use std::borrow::Cow;
 use std::iter;
 use std::sync::Arc;
 use bytemuck::{Pod, Zeroable};
-use cgmath::{ Matrix4, Point3};
+use cgmath::{ Matrix4, Point3, SquareMatrix};
 use wgpu::util::DeviceExt;
 use wgpu::MemoryHints::Performance;
 use wgpu::ShaderSource;
 use winit::window::Window;
 use crate::player;
+use crate::terrain::noise::{NoiseParams, NoiseSeed};
+use crate::light::Light;
+use crate::biome::BiomeUniform;
+use crate::water::WaterUniform;
+use crate::fog::FogUniform;
+use crate::shadow::{self, ShadowUniform, CASCADES};
+use crate::hud::{HudUniform, SpeedIndicator};
 
 
-#[allow(unused)] // TODO remove this once it is used
 pub struct WgpuContext<'window> {
-    surface: wgpu::Surface<'window>,
+    surface: Option<wgpu::Surface<'window>>, // None when rendering headless
+    // also describes the offscreen target when there's no surface
     surface_config: wgpu::SurfaceConfiguration,
-    adapter: wgpu::Adapter,
     device: wgpu::Device,
     queue: wgpu::Queue,
     render_pipeline: wgpu::RenderPipeline,
+    sky_pipeline: wgpu::RenderPipeline, // fills whatever the terrain didn't cover
+    water_pipeline: wgpu::RenderPipeline, // drawn over the terrain, blended
+    shadow_pipeline: wgpu::RenderPipeline, // the terrain into the shadow map, before everything else
+    hud_pipeline: wgpu::RenderPipeline, // the speed indicator, last and only in the window
     uniform_bind_group: wgpu::BindGroup,
+    shadow_map_layout: wgpu::BindGroupLayout,
+    shadow_map: ShadowMap,
+    cascade_bind_groups: Vec<wgpu::BindGroup>, // the cascade index for the shadow pass, one per cascade
     model_mat: Matrix4<f32>,
     view_mat: Matrix4<f32>,
     project_mat: Matrix4<f32>,
-    vertex_buffer: wgpu::Buffer, 
     uniform_buffer: wgpu::Buffer,
-    vertex_data: VertexData
+    camera_position: [f32; 4], // in world space, the shader morphs chunks by their distance to it
+    grid: GpuGrid,
+    chunks: Vec<ChunkInstance>, // the ones lod::select picked for this frame, closest first
+    instance_buffer: wgpu::Buffer, // chunks, grows when there are more of them
+    noise_params: NoiseParams,
+    noise_seed: NoiseSeed,
+    light: Light,
+    shadow_mats: [Matrix4<f32>; CASCADES],
+    shadow_uniform: ShadowUniform,
+    shadow_casters: [u32; CASCADES], // how many of the closest chunks each cascade draws
+    speed_indicator: SpeedIndicator,
+    time: f32, // seconds since start, for anything animated
+    settings: Settings
+}
+
+// the vertex grid all chunks share, only changes with the quality
+struct GpuGrid {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32
+}
+
+impl GpuGrid {
+    fn new(device: &wgpu::Device, vertex_data: &VertexData) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Grid Vertex Buffer"),
+            contents: bytemuck::cast_slice(&create_vertices(&vertex_data.positions)),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Grid Index Buffer"),
+            contents: bytemuck::cast_slice(&vertex_data.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        return Self { vertex_buffer, index_buffer, index_count: vertex_data.indices.len() as u32 };
+    }
+}
+
+// one depth layer per cascade, group 1 of the terrain pipeline samples all of them
+struct ShadowMap {
+    resolution: u32,
+    layer_views: Vec<wgpu::TextureView>, // to render each cascade into
+    bind_group: wgpu::BindGroup
+}
+
+impl ShadowMap {
+    fn new(device: &wgpu::Device, layout: &wgpu::BindGroupLayout, resolution: u32) -> Self {
+        let resolution = resolution.clamp(1, device.limits().max_texture_dimension_2d);
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d { width: resolution, height: resolution, depth_or_array_layers: CASCADES as u32 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Depth32Float,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            label: Some("Shadow Map"),
+            view_formats: &[]
+        });
+        let layer_views = (0..CASCADES as u32).map(|layer| {
+            return texture.create_view(&wgpu::TextureViewDescriptor {
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+                ..Default::default()
+            });
+        }).collect();
+        let array_view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
+            ..Default::default()
+        });
+        // linear comparisons give a bit of filtering for free on top of the taps in the shader
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Shadow Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            compare: Some(wgpu::CompareFunction::LessEqual),
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&array_view) },
+                wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&sampler) }
+            ],
+            label: Some("Shadow Map Bind Group")
+        });
+        return Self { resolution, layer_views, bind_group };
+    }
+}
+
+// where the shader puts the grid for a chunk and how to morph it into the next lod level
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Pod, Zeroable)]
+struct ChunkInstance {
+    placement: [f32; 4], // origin x, origin z, size, unused
+    morph: [f32; 4] // quad size, distance where morphing starts, distance where it ends, unused
+}
+
+impl ChunkInstance {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![1 => Float32x4, 2 => Float32x4];
+
+    fn new(quality: Quality, coord: ChunkCoord) -> Self {
+        let (origin, size) = (coord.origin(), coord.size());
+        let (morph_start, morph_end) = lod::morph_range(coord.level);
+        return Self {
+            placement: [origin[0], origin[1], size, 0.],
+            morph: [size / quality.quads_per_chunk() as f32, morph_start, morph_end, 0.]
+        };
+    }
+
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ChunkInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES
+        }
+    }
 }
 
 #[repr(C)]
@@ -43,12 +175,15 @@ fn vertex(pos: [f32; 3]) -> Vertex {
     };
 }
 
-fn create_vertices(pos: &Vec<[f32; 3]>) -> Vec<Vertex> {
-    let mut vdata: Vec<Vertex> = Vec::with_capacity(pos.len());
-    for i in 0..pos.len() {
-        vdata.push(vertex(pos[i]));
-    }
-    return vdata.to_vec();
+fn create_vertices(pos: &[[f32; 3]]) -> Vec<Vertex> {
+    return pos.iter().map(|&p| vertex(p)).collect();
+}
+
+// takes a point on the far plane in clip space back to world space, the sky shader gets its view directions from it
+fn sky_matrix(view_mat: Matrix4<f32>, project_mat: Matrix4<f32>) -> [f32; 16] {
+    let inverse = (project_mat * view_mat).invert().unwrap_or(Matrix4::identity());
+    let mat_4x4: [[f32; 4]; 4] = inverse.into();
+    return mat_4x4.concat().try_into().unwrap();
 }
 
 impl Vertex {
@@ -63,10 +198,9 @@ impl Vertex {
 }
 
 impl<'window> WgpuContext<'window> {
-    pub async fn new_async(window: Arc<Window>) -> WgpuContext<'window> {
-
+    pub async fn new_async(window: Arc<Window>, settings: &Settings) -> Result<WgpuContext<'window>, TerrainError> {
         let instance = wgpu::Instance::default();
-        let surface = instance.create_surface(Arc::clone(&window)).unwrap();
+        let surface = instance.create_surface(Arc::clone(&window))?;
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
                 power_preference: wgpu::PowerPreference::default(),
@@ -75,8 +209,45 @@ impl<'window> WgpuContext<'window> {
                 compatible_surface: Some(&surface),
             })
             .await
-            .expect("Failed to find an appropriate adapter");
-        
+            .ok_or(TerrainError::NoAdapter)?;
+
+        let size = window.inner_size();
+        let width = size.width.max(1);
+        let height = size.height.max(1);
+        let surface_config = surface.get_default_config(&adapter, width, height).ok_or(TerrainError::UnsupportedSurface)?;
+        return Self::create(adapter, Some(surface), surface_config, settings).await;
+    }
+
+    // no window, frames only go to capture(), fallback_adapter asks for a software renderer (for build servers)
+    pub async fn new_headless_async(width: u32, height: u32, settings: &Settings, fallback_adapter: bool) -> Result<WgpuContext<'static>, TerrainError> {
+        let instance = wgpu::Instance::default();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::default(),
+                force_fallback_adapter: fallback_adapter,
+                compatible_surface: None,
+            })
+            .await
+            .ok_or(TerrainError::NoAdapter)?;
+
+        let target_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![]
+        };
+        return WgpuContext::create(adapter, None, target_config, settings).await;
+    }
+
+    async fn create(adapter: wgpu::Adapter, surface: Option<wgpu::Surface<'window>>, surface_config: wgpu::SurfaceConfiguration, settings: &Settings) -> Result<WgpuContext<'window>, TerrainError> {
+
+        let noise_params = settings.noise;
+        let noise_seed = NoiseSeed::from_seed(settings.seed);
+
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
@@ -89,16 +260,12 @@ impl<'window> WgpuContext<'window> {
                 },
                 None,
             )
-            .await
-            .expect("Failed to create device");
-
-        let size = window.inner_size();
-        let width = size.width.max(1);
-        let height = size.height.max(1);
-        let surface_config = surface.get_default_config(&adapter, width, height).unwrap();
-        surface.configure(&device, &surface_config);
+            .await?;
 
-        //= Self::create_pipeline(&device, surface_config.format, width, height);
+        let (width, height) = (surface_config.width, surface_config.height);
+        if let Some(surface) = surface.as_ref() {
+            surface.configure(&device, &surface_config);
+        }
 
         let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some("Shader"),
@@ -107,17 +274,29 @@ impl<'window> WgpuContext<'window> {
 
         let model_mat = transforms::create_transforms([0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [1.0, 1.0, 1.0]);
         let view_mat = transforms::create_view((0., 0., 0.).into(), (0., 0., 0.).into(), cgmath::Vector3::unit_y());
-        let project_mat = transforms::create_projection(width as f32 / height as f32, true);
+        let project_mat = transforms::create_projection(width as f32 / height as f32, settings.fov, true);
+        let camera_position = [0., 0., 0., 1.];
 
         let mut uniforms = Vec::<f32>::new(); 
         for mat in [model_mat, view_mat, project_mat] {
             let mat_4x4: [[f32; 4]; 4] = mat.into();
             uniforms.extend(mat_4x4.concat());
         }
-        uniforms.push(0.);
-        uniforms.push(0.);
-        uniforms.push(0.);
-        uniforms.push(0.);
+        uniforms.extend(bytemuck::cast::<NoiseParams, [f32; 8]>(noise_params));
+        uniforms.extend(bytemuck::cast::<NoiseSeed, [f32; 4]>(noise_seed));
+        uniforms.extend(camera_position);
+        uniforms.extend(bytemuck::cast::<Light, [f32; 12]>(settings.light));
+        uniforms.extend(bytemuck::cast_slice::<BiomeUniform, f32>(&[settings.biomes.uniform()]));
+        uniforms.extend(bytemuck::cast::<WaterUniform, [f32; 12]>(settings.water.uniform(0.)));
+        uniforms.extend(sky_matrix(view_mat, project_mat));
+        uniforms.extend(bytemuck::cast::<FogUniform, [f32; 4]>(settings.fog.uniform(settings.render_distance.radius())));
+        let shadow_mats = [Matrix4::identity(); CASCADES];
+        for mat in shadow_mats {
+            let mat_4x4: [[f32; 4]; 4] = mat.into();
+            uniforms.extend(mat_4x4.concat());
+        }
+        uniforms.extend(bytemuck::cast::<ShadowUniform, [f32; 4]>(ShadowUniform::default()));
+        uniforms.extend(bytemuck::cast::<HudUniform, [f32; 4]>(HudUniform::default()));
 
         let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Uniform Buffer"),
@@ -128,7 +307,7 @@ impl<'window> WgpuContext<'window> {
         let uniform_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
+                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Buffer { 
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -148,9 +327,64 @@ impl<'window> WgpuContext<'window> {
             label: Some("Bind Group")
         });
 
+        let shadow_map_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension: wgpu::TextureViewDimension::D2Array,
+                        multisampled: false
+                    },
+                    count: None
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None
+                }
+            ],
+            label: Some("Shadow Map Bind Group Layout")
+        });
+        let shadow_map = ShadowMap::new(&device, &shadow_map_layout, Self::shadow_resolution(settings));
+
+        let cascade_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::VERTEX,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None
+                },
+                count: None
+            }],
+            label: Some("Cascade Bind Group Layout")
+        });
+        let cascade_bind_groups = (0..CASCADES as u32).map(|index| {
+            // padded to 16 bytes, the smallest uniform buffer everywhere
+            let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Cascade Buffer"),
+                contents: bytemuck::cast_slice(&[index, 0, 0, 0]),
+                usage: wgpu::BufferUsages::UNIFORM
+            });
+            return device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: &cascade_layout,
+                entries: &[wgpu::BindGroupEntry { binding: 2, resource: buffer.as_entire_binding() }],
+                label: Some("Cascade Bind Group")
+            });
+        }).collect();
+
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: None,
-            bind_group_layouts: &[&uniform_bind_group_layout],
+            bind_group_layouts: &[&uniform_bind_group_layout, &shadow_map_layout],
+            push_constant_ranges: &[],
+        });
+        let shadow_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Shadow Pipeline Layout"),
+            bind_group_layouts: &[&uniform_bind_group_layout, &cascade_layout],
             push_constant_ranges: &[],
         });
 
@@ -160,7 +394,7 @@ impl<'window> WgpuContext<'window> {
             vertex: wgpu::VertexState {
                 module: &shader,
                 entry_point: "vs_main",
-                buffers: &[Vertex::desc()],
+                buffers: &[Vertex::desc(), ChunkInstance::desc()],
                 compilation_options: Default::default()
             },
             fragment: Some(wgpu::FragmentState {
@@ -168,7 +402,7 @@ impl<'window> WgpuContext<'window> {
                 entry_point: "fs_main",
                 compilation_options: Default::default(),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_config.format.into(),
+                    format: surface_config.format,
                     blend: Some(wgpu::BlendState { 
                         color: wgpu::BlendComponent::REPLACE, 
                         alpha: wgpu::BlendComponent::REPLACE 
@@ -177,7 +411,7 @@ impl<'window> WgpuContext<'window> {
                 })]
             }),
             primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                topology: wgpu::PrimitiveTopology::TriangleList,
                 strip_index_format: None,
                 ..Default::default()
             },
@@ -197,81 +431,465 @@ impl<'window> WgpuContext<'window> {
             cache: None
         });
 
-        let vertex_data = VertexData::new();
-    
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(&create_vertices(&vertex_data.positions)),
-            usage: wgpu::BufferUsages::VERTEX,
+        // a fullscreen triangle on the far plane, so it only shows where nothing else was drawn
+        let sky_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sky Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_sky",
+                buffers: &[],
+                compilation_options: Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_sky",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_config.format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL
+                })]
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth24Plus,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default()
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 4,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None
         });
 
-        return WgpuContext {
+        // tests against the terrain depth but doesn't write it, premultiplied so it can both tint and reflect
+        let water_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Water Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_water",
+                buffers: &[Vertex::desc(), ChunkInstance::desc()],
+                compilation_options: Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_water",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_config.format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL
+                })]
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth24Plus,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default()
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 4,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None
+        });
+
+        // no fragment shader, only the depth is needed, biased so lit slopes don't shadow themselves
+        let shadow_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(&shadow_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_shadow",
+                buffers: &[Vertex::desc(), ChunkInstance::desc()],
+                compilation_options: Default::default()
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth32Float,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.,
+                    clamp: 0.
+                }
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None
+        });
+
+        // on top of everything, so no depth test
+        let hud_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Hud Pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_hud",
+                buffers: &[],
+                compilation_options: Default::default()
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_hud",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: surface_config.format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL
+                })]
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: wgpu::TextureFormat::Depth24Plus,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default()
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 4,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None
+        });
+
+        let grid = GpuGrid::new(&device, &VertexData::chunk_grid(settings.quality));
+        let instance_buffer = Self::create_instance_buffer(&device, 64);
+
+        return Ok(WgpuContext {
             surface,
             surface_config,
-            adapter,
             device,
             queue,
             render_pipeline,
+            sky_pipeline,
+            water_pipeline,
+            shadow_pipeline,
+            hud_pipeline,
             uniform_bind_group,
+            shadow_map_layout,
+            shadow_map,
+            cascade_bind_groups,
             model_mat,
             view_mat,
             project_mat,
-            vertex_buffer,
             uniform_buffer,
-            vertex_data
-        };
+            camera_position,
+            grid,
+            chunks: Vec::new(),
+            instance_buffer,
+            noise_params,
+            noise_seed,
+            light: settings.light,
+            shadow_mats,
+            shadow_uniform: ShadowUniform::default(),
+            shadow_casters: [0; CASCADES],
+            speed_indicator: SpeedIndicator::default(),
+            time: 0.,
+            settings: settings.clone()
+        });
+    }
+
+    pub fn new(window: Arc<Window>, settings: &Settings) -> Result<WgpuContext<'window>, TerrainError> {
+        return pollster::block_on(WgpuContext::new_async(window, settings));
+    }
+
+    pub fn new_headless(width: u32, height: u32, settings: &Settings, fallback_adapter: bool) -> Result<WgpuContext<'static>, TerrainError> {
+        return pollster::block_on(WgpuContext::new_headless_async(width, height, settings, fallback_adapter));
+    }
+
+    // uniforms and chunks are picked up by the next update, the grid is only rebuilt if the quality actually changed
+    pub fn apply_settings(&mut self, settings: &Settings) {
+        if settings.quality != self.settings.quality {
+            self.grid = GpuGrid::new(&self.device, &VertexData::chunk_grid(settings.quality));
+        }
+        if Self::shadow_resolution(settings) != Self::shadow_resolution(&self.settings) {
+            self.shadow_map = ShadowMap::new(&self.device, &self.shadow_map_layout, Self::shadow_resolution(settings));
+        }
+        self.noise_params = settings.noise;
+        self.noise_seed = NoiseSeed::from_seed(settings.seed);
+        self.settings = settings.clone();
     }
 
-    pub fn new(window: Arc<Window>) -> WgpuContext<'window> {
-        return pollster::block_on(WgpuContext::new_async(window));
+    // with shadows off the map is still bound, it just doesn't need to be big
+    fn shadow_resolution(settings: &Settings) -> u32 {
+        return if settings.shadows.enabled { settings.shadows.resolution } else { 1 };
+    }
+
+    // the sun moves with the time of day, picked up by the next update
+    pub fn set_light(&mut self, light: Light) {
+        self.light = light;
+    }
+
+    // picked up by the next update
+    pub fn set_speed_indicator(&mut self, speed_indicator: SpeedIndicator) {
+        self.speed_indicator = speed_indicator;
     }
 
     pub fn resize(&mut self, new_size: (u32, u32)) {
         let (width, height) = new_size;
         self.surface_config.width = width.max(1);
         self.surface_config.height = height.max(1);
-        self.surface.configure(&self.device, &self.surface_config);
+        if let Some(surface) = self.surface.as_ref() {
+            surface.configure(&self.device, &self.surface_config);
+        }
 
-        self.project_mat = transforms::create_projection(width as f32 / height as f32, true);
+        self.project_mat = transforms::create_projection(self.surface_config.width as f32 / self.surface_config.height as f32, self.settings.fov, true);
     }
 
-    pub fn update(&mut self, _dt: std::time::Duration, player: &player::Player) {
+    pub fn update(&mut self, dt: std::time::Duration, player: &player::Player) {
+        self.time += dt.as_secs_f32();
 
         let mut camera_look_direction: Vector3 = utils::rotation_to_direction(player.smooth_camera_rotation);
         camera_look_direction.x = -camera_look_direction.x;
         
         self.view_mat = transforms::create_view(Point3::new(0.0, 0.0, -0.0000001), Point3::from(camera_look_direction), cgmath::Vector3::unit_y());
-        self.project_mat = transforms::create_projection(self.surface_config.width as f32 / self.surface_config.height as f32, true);
-        self.model_mat = transforms::create_transforms(<[f32; 3]>::from(player.get_relative_position(Vector3::new(player.player_position.x - 2.5 * vertex_data::render_dist_mul(), -5., player.player_position.z - 2.5 * vertex_data::render_dist_mul()))), [0., 0., 0.], [1., 1., 1.]);
+        self.project_mat = transforms::create_projection(self.surface_config.width as f32 / self.surface_config.height as f32, self.settings.fov, true);
+        // the terrain is in world space, this moves the world so the camera ends up at the origin
+        let camera_offset = player.get_relative_position(Vector3::new(0., -player::CAMERA_HEIGHT, 0.));
+        self.model_mat = transforms::create_transforms(<[f32; 3]>::from(camera_offset), [0., 0., 0.], [1., 1., 1.]);
+        self.camera_position = [-camera_offset.x, -camera_offset.y, -camera_offset.z, 1.];
+
+        // has to be the same position the shader morphs with, otherwise there can be cracks between lod levels
+        let quality = self.settings.quality;
+        let center = [self.camera_position[0], self.camera_position[2]];
+        let radius = self.settings.render_distance.radius();
+        let selected = lod::select(center, radius);
+        self.chunks = selected.iter().map(|&coord| {
+            return ChunkInstance::new(quality, coord);
+        }).collect();
+
+        // no point in shadows once the sun is gone
+        if self.settings.shadows.enabled && self.light.direction[1] > 0. {
+            let ground = [center[0], 0.5 * self.noise_params.amplitude, center[1]];
+            let cascades = shadow::cascades(ground, self.light.direction, radius, self.shadow_map.resolution);
+            self.shadow_mats = cascades.each_ref().map(|cascade| cascade.matrix);
+            self.shadow_uniform = ShadowUniform::new(&cascades, self.shadow_map.resolution);
+            // chunks are sorted by distance, so each cascade only draws the front of the list
+            // a bit further than the cascade reaches, hills just outside of it can still throw shadows into it
+            self.shadow_casters = cascades.each_ref().map(|cascade| {
+                return selected.iter().take_while(|coord| coord.distance_to(center) <= cascade.radius + shadow::CASTER_MARGIN).count() as u32;
+            });
+        } else {
+            self.shadow_uniform = ShadowUniform::default();
+            self.shadow_casters = [0; CASCADES];
+        }
+        self.write_uniforms(self.project_mat);
 
+        let needed = (self.chunks.len() * std::mem::size_of::<ChunkInstance>()) as wgpu::BufferAddress;
+        if needed > self.instance_buffer.size() {
+            self.instance_buffer = Self::create_instance_buffer(&self.device, self.chunks.len().next_power_of_two());
+        }
+        self.queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&self.chunks));
+    }
+
+    fn create_instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        return device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Chunk Instance Buffer"),
+            size: (capacity * std::mem::size_of::<ChunkInstance>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false
+        });
+    }
+
+    // the projection is a parameter so capture_tiled can render parts of the view
+    fn write_uniforms(&self, project_mat: Matrix4<f32>) {
         let mut uniforms = Vec::<f32>::new(); 
-        for mat in [self.model_mat, self.view_mat, self.project_mat] {
+        for mat in [self.model_mat, self.view_mat, project_mat] {
             let mat_4x4: [[f32; 4]; 4] = mat.into();
             uniforms.extend(mat_4x4.concat());
         }
-        uniforms.push(player.player_position.x as f32);
-        uniforms.push(player.player_position.z as f32);
-        uniforms.push(0. as f32);
-        uniforms.push(0. as f32);
+        uniforms.extend(bytemuck::cast::<NoiseParams, [f32; 8]>(self.noise_params));
+        uniforms.extend(bytemuck::cast::<NoiseSeed, [f32; 4]>(self.noise_seed));
+        uniforms.extend(self.camera_position);
+        uniforms.extend(bytemuck::cast::<Light, [f32; 12]>(self.light));
+        uniforms.extend(bytemuck::cast_slice::<BiomeUniform, f32>(&[self.settings.biomes.uniform()]));
+        uniforms.extend(bytemuck::cast::<WaterUniform, [f32; 12]>(self.settings.water.uniform(self.time)));
+        uniforms.extend(sky_matrix(self.view_mat, project_mat));
+        uniforms.extend(bytemuck::cast::<FogUniform, [f32; 4]>(self.settings.fog.uniform(self.settings.render_distance.radius())));
+        for mat in self.shadow_mats {
+            let mat_4x4: [[f32; 4]; 4] = mat.into();
+            uniforms.extend(mat_4x4.concat());
+        }
+        uniforms.extend(bytemuck::cast::<ShadowUniform, [f32; 4]>(self.shadow_uniform));
+        let screen = [self.surface_config.width as f32, self.surface_config.height as f32];
+        uniforms.extend(bytemuck::cast::<HudUniform, [f32; 4]>(self.speed_indicator.uniform(screen)));
         self.queue.write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&uniforms));
     }
 
-    pub fn draw(&mut self) {
-        let surface_texture = self.surface.get_current_texture().expect("Failed to acquire next swap chain texture");
+    pub fn draw(&mut self) -> Result<(), TerrainError> {
+        let Some(surface) = self.surface.as_ref() else {
+            return Ok(());
+        };
+        let surface_texture = match surface.get_current_texture() {
+            Ok(surface_texture) => surface_texture,
+            Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                // happens on resize and minimize on some platforms, skip this frame and try again with a fresh surface
+                surface.configure(&self.device, &self.surface_config);
+                return Ok(());
+            }
+            Err(wgpu::SurfaceError::Timeout) => {
+                return Ok(());
+            }
+            Err(err) => {
+                return Err(TerrainError::Surface(err));
+            }
+        };
         let texture_view = surface_texture.texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.render(&texture_view, true);
+        surface_texture.present();
+        return Ok(());
+    }
+
+    // renders the current view at scale times the resolution, as scale * scale tiles of the normal size stitched together
+    // returns width, height and rgba8 pixels
+    pub fn capture_tiled(&mut self, scale: u32) -> Result<(u32, u32, Vec<u8>), TerrainError> {
+        let (width, height) = (self.surface_config.width, self.surface_config.height);
+        if scale <= 1 {
+            return Ok((width, height, self.capture()?));
+        }
+
+        // the scale is limited, but a big window can still push it past what fits in memory
+        let (full_width, full_height) = (width as u64 * scale as u64, height as u64 * scale as u64);
+        if full_width * full_height > ScreenshotSettings::MAX_PIXELS {
+            return Err(TerrainError::TooLarge(format!("a {}x{} screenshot at scale {}", full_width, full_height, scale)));
+        }
+        let (full_width, full_height) = (full_width as u32, full_height as u32);
+        let mut image = vec![0u8; full_width as usize * full_height as usize * 4];
+        let n = scale as f32;
+        for tile_y in 0..scale {
+            for tile_x in 0..scale {
+                // scale up clip space around the center of this tile, so only it ends up on the screen
+                let center_x = -1. + (2. * tile_x as f32 + 1.) / n;
+                let center_y = 1. - (2. * tile_y as f32 + 1.) / n;
+                let tile_mat = Matrix4::new(
+                    n, 0., 0., 0.,
+                    0., n, 0., 0.,
+                    0., 0., 1., 0.,
+                    -n * center_x, -n * center_y, 0., 1.
+                );
+                self.write_uniforms(tile_mat * self.project_mat);
+                let tile = self.capture()?;
+
+                let row_bytes = (width * 4) as usize;
+                for (row, pixels) in tile.chunks(row_bytes).enumerate() {
+                    let start = (((tile_y * height) as usize + row) * full_width as usize + (tile_x * width) as usize) * 4;
+                    image[start..start + row_bytes].copy_from_slice(pixels);
+                }
+            }
+        }
+        self.write_uniforms(self.project_mat);
+        return Ok((full_width, full_height, image));
+    }
 
+    // draws a frame into an offscreen texture instead of the window and reads it back as tightly packed rgba8
+    // not a copy of what draw() presented: the surface texture isn't guaranteed to allow COPY_SRC, screenshots leave out
+    // the hud, and capture_tiled needs frames with other projections anyway
+    pub fn capture(&mut self) -> Result<Vec<u8>, TerrainError> {
+        // window surfaces are usually bgra, those get swapped to rgba at the end
+        let bgra = match self.surface_config.format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => false,
+            wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => true,
+            format => {
+                return Err(TerrainError::CaptureFormat(format));
+            }
+        };
+        let (width, height) = (self.surface_config.width, self.surface_config.height);
+        let target_texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.surface_config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            label: Some("Capture Texture"),
+            view_formats: &[]
+        });
+        // screenshots are without the hud
+        self.render(&target_texture.create_view(&wgpu::TextureViewDescriptor::default()), false);
+
+        // rows in a texture to buffer copy have to be a multiple of 256 bytes
+        let row_bytes = width * 4;
+        let padded_row_bytes = row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let readback_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: padded_row_bytes as wgpu::BufferAddress * height as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false
+        });
+
+        let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Capture Encoder") });
+        encoder.copy_texture_to_buffer(
+            target_texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &readback_buffer,
+                layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(padded_row_bytes), rows_per_image: Some(height) }
+            },
+            target_texture.size()
+        );
+        self.queue.submit(iter::once(encoder.finish()));
+
+        let slice = readback_buffer.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        receiver.recv().map_err(|_| TerrainError::Readback(wgpu::BufferAsyncError))?.map_err(TerrainError::Readback)?;
+
+        let mut pixels = Vec::<u8>::with_capacity(row_bytes as usize * height as usize);
+        for row in slice.get_mapped_range().chunks(padded_row_bytes as usize) {
+            pixels.extend_from_slice(&row[..row_bytes as usize]);
+        }
+        readback_buffer.unmap();
+
+        if bgra {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        return Ok(pixels);
+    }
+
+    fn render(&self, target_view: &wgpu::TextureView, hud: bool) {
         let multisample_texture = self.device.create_texture(&wgpu::TextureDescriptor{
-            format: surface_texture.texture.format(),
+            format: self.surface_config.format,
             sample_count: 4,
-            size: surface_texture.texture.size(),
-            usage: surface_texture.texture.usage(),
-            mip_level_count: surface_texture.texture.mip_level_count(),
+            size: wgpu::Extent3d {
+                width: self.surface_config.width,
+                height: self.surface_config.height,
+                depth_or_array_layers: 1
+            },
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            mip_level_count: 1,
             label: Some("Multisample Texture"),
-            dimension: surface_texture.texture.dimension(),
+            dimension: wgpu::TextureDimension::D2,
             view_formats: &[]
         });
         let multisample_view = multisample_texture.create_view(&wgpu::TextureViewDescriptor::default());
-
         let depth_texture = self.device.create_texture(&wgpu::TextureDescriptor {
             size: wgpu::Extent3d {
                 width: self.surface_config.width,
@@ -289,12 +907,38 @@ impl<'window> WgpuContext<'window> {
         let depth_view = depth_texture.create_view(&wgpu::TextureViewDescriptor::default() );
 
         let mut encoder = self.device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("Command Encoder") }); 
+        for (cascade, &casters) in self.shadow_casters.iter().enumerate() {
+            if casters == 0 {
+                continue;
+            }
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.shadow_map.layer_views[cascade],
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store
+                    }),
+                    stencil_ops: None
+                }),
+                timestamp_writes: None,
+                occlusion_query_set: None
+            });
+            pass.set_pipeline(&self.shadow_pipeline);
+            pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            pass.set_bind_group(1, &self.cascade_bind_groups[cascade], &[]);
+            pass.set_vertex_buffer(0, self.grid.vertex_buffer.slice(..));
+            pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            pass.set_index_buffer(self.grid.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            pass.draw_indexed(0..self.grid.index_count, 0, 0..casters);
+        }
         {
             let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view: &multisample_view,
-                    resolve_target: Some(&texture_view),
+                    resolve_target: Some(target_view),
                     ops: wgpu::Operations {
                         load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
                         store: wgpu::StoreOp::Store
@@ -313,14 +957,28 @@ impl<'window> WgpuContext<'window> {
             });
 
             pass.set_pipeline(&self.render_pipeline);
-            pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
             pass.set_bind_group(0, &self.uniform_bind_group, &[]);
-            pass.draw(0..self.vertex_data.length, 0..1);
+            pass.set_bind_group(1, &self.shadow_map.bind_group, &[]);
+            // every chunk is an instance of the same grid
+            pass.set_vertex_buffer(0, self.grid.vertex_buffer.slice(..));
+            pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+            pass.set_index_buffer(self.grid.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            pass.draw_indexed(0..self.grid.index_count, 0, 0..self.chunks.len() as u32);
+
+            pass.set_pipeline(&self.sky_pipeline);
+            pass.draw(0..3, 0..1);
+
+            // the sea is the same grid again, flat at sea level, after the terrain so it can blend over it
+            pass.set_pipeline(&self.water_pipeline);
+            pass.draw_indexed(0..self.grid.index_count, 0, 0..self.chunks.len() as u32);
+
+            if hud {
+                pass.set_pipeline(&self.hud_pipeline);
+                pass.draw(0..6, 0..1);
+            }
         }
 
         self.queue.submit(iter::once(encoder.finish()));
-        surface_texture.present();
-
     }
 }
 